@@ -3,7 +3,28 @@
 # be added to the unit testing executable.
 file(GLOB_RECURSE TEST_SOURCE_FILES ${CMAKE_SOURCE_DIR}/test/*.cpp)
 
-add_executable(unit_test ${SOURCE_FILES_NO_MAIN} ${TEST_SOURCE_FILES})
+# Only the modules that don't need a running simavr core are tested
+set(UNIT_TEST_SOURCE_FILES
+  ${CMAKE_SOURCE_DIR}/src/arduous/display_commands.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/elf_symbols.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/flashcart.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/ihex.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/ram_search.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/rom_id.cpp
+  ${CMAKE_SOURCE_DIR}/src/arduous/watch.cpp
+  ${CMAKE_SOURCE_DIR}/simavr/simavr/sim/sim_hex.c
+  )
+
+add_executable(unit_test ${UNIT_TEST_SOURCE_FILES} ${TEST_SOURCE_FILES})
+target_include_directories(
+    unit_test
+    PRIVATE include
+    PRIVATE ${CMAKE_SOURCE_DIR}/simavr/simavr/sim
+)
+set_property(TARGET unit_test PROPERTY C_STANDARD 99)
+
+# The linker script only exports the libretro API from the core; it isn't meant for executables
+set(CMAKE_EXE_LINKER_FLAGS "")
 
 # Enable CMake `make test` support.
 enable_testing()
@@ -5,8 +5,20 @@
 #include <bitset>
 #include <cstdint>
 #include <cstdio>
+#include <deque>
+#include <functional>
+#include <map>
+#include <memory>
+#include <string>
 #include <vector>
 
+#include "arduous/display_commands.h"
+#include "arduous/flashcart.h"
+#include "arduous/fx_flash.h"
+#include "arduous/hardware.h"
+#include "arduous/log.h"
+#include "arduous/rom_id.h"
+#include "arduous/watch.h"
 #include "sim_avr.h"
 extern "C" {
 #include "ssd1306_virt.h"
@@ -14,8 +26,8 @@ extern "C" {
 
 constexpr unsigned int TIMING_FPS = 60;
 constexpr unsigned int TIMING_SAMPLE_RATE = 48000;
-constexpr unsigned int DISPLAY_WIDTH = 128;
-constexpr unsigned int DISPLAY_HEIGHT = 64;
+// ADC input hook channel used for the internal temperature sensor; 0-15 are the regular ADC inputs
+constexpr int ADC_CHANNEL_TEMPERATURE = 16;
 
 struct ArduousButtonState {
     bool buttonUp = false;
@@ -26,62 +38,507 @@ struct ArduousButtonState {
     bool buttonB = false;
 };
 
+enum class ArduousButton { Up, Right, Left, Down, A, B };
+
+// Whole-controller button state in one byte, using the same bits as Arduboy2's button masks
+constexpr uint8_t ARDUOUS_BUTTON_UP = 1 << 7;
+constexpr uint8_t ARDUOUS_BUTTON_RIGHT = 1 << 6;
+constexpr uint8_t ARDUOUS_BUTTON_LEFT = 1 << 5;
+constexpr uint8_t ARDUOUS_BUTTON_DOWN = 1 << 4;
+constexpr uint8_t ARDUOUS_BUTTON_A = 1 << 3;
+constexpr uint8_t ARDUOUS_BUTTON_B = 1 << 2;
+
+// Fuse bytes as shipped on production Arduboys (Caterina, 4 KB boot section, BOOTRST programmed)
+struct ArduousFuses {
+    uint8_t low = 0xFF;
+    uint8_t high = 0xD0;
+    uint8_t extended = 0xCB;
+};
+
+enum class ArduousLoadResult { Ok, InvalidHex, TooLarge, OverlapsBootloader, InvalidFlashcart };
+
+enum class ArduousResetCause { PowerOn, External, BrownOut, Watchdog };
+
+// PointSampled takes the speaker level at each sample; BandLimited averages it over the sample period, which
+// keeps high tones from aliasing
+enum class ArduousAudioSynthesis { PointSampled, BandLimited };
+
+// The piezo sits between PC6 and PC7. Differential plays the voltage across it on both channels (equal pins are
+// silent, opposite pins are Arduboy2's high volume); PinsToStereo puts PC6 on the left and PC7 on the right
+// for diagnostics.
+enum class ArduousAudioChannels { Differential, PinsToStereo };
+
+enum class ArduousEventType {
+    DisplayFrameReady,  // value: frame number
+    SerialByte,         // value: byte sent on USART1
+    EepromWrite,        // value: EEPROM address in the low 16 bits, data byte in bits 16-23
+    LedChanged,         // value: ARDUOUS_LED_* bits of the LEDs now lit
+    AudioBlock,         // value: stereo samples available from getAudioBuffer()
+    Breakpoint,         // value: program counter
+    StackFault,         // value: stack pointer, after it left SRAM
+    DisplayUpdated,     // value: number of display updates, each a full burst of display data from the game
+    ToneStarted,        // value: speaker pin levels, PC6 in bit 0 and PC7 in bit 1
+    ToneStopped,        // value: 0, once the speaker pins have been still for a while
+    BootloaderEntered,  // value: PC, on jumping or resetting into the boot section
+};
+
+struct ArduousEvent {
+    ArduousEventType type;
+    uint64_t cycle;
+    uint32_t value;
+};
+
+constexpr uint32_t ARDUOUS_LED_RED = 1 << 0;
+constexpr uint32_t ARDUOUS_LED_GREEN = 1 << 1;
+constexpr uint32_t ARDUOUS_LED_BLUE = 1 << 2;
+constexpr uint32_t ARDUOUS_LED_TX = 1 << 3;
+constexpr uint32_t ARDUOUS_LED_RX = 1 << 4;
+
+struct ArduousPcHistoryEntry {
+    uint32_t pc;
+    uint16_t sp;
+    uint8_t sreg;
+};
+
+struct ArduousAuditEntry {
+    std::string message;
+    uint32_t pc;
+    uint64_t count;
+};
+
+// A horizontal run of pixels that changed to the same value since the previous frame.
+struct ArduousPixelRun {
+    unsigned int x;
+    unsigned int y;
+    unsigned int length;
+    bool lit;
+};
+
+// What a call to emulateFrame() actually ran, so frontends can check pacing
+struct ArduousFrameStats {
+    uint64_t cycles;
+    uint64_t instructions;
+};
+
+// Brightness of each RGB LED channel over the last frame, so PWM-dimmed colors come through
+struct ArduousRgbLed {
+    uint8_t red;
+    uint8_t green;
+    uint8_t blue;
+};
+
+enum class ArduousDisplayAddressing { Horizontal, Vertical, Page };
+
+// What the game left the display controller set to, for diagnosing blank or garbled screens
+struct ArduousDisplayState {
+    ArduousDisplayAddressing addressing;
+    uint8_t cursorPage;
+    uint8_t cursorColumn;
+    uint8_t contrast;
+    bool displayOn;  // false while the controller is asleep (0xAE)
+    bool inverted;
+    bool segmentRemap;     // 0xA1, column 127 drives the leftmost segment
+    bool comScanReversed;  // 0xC8, rows scan bottom to top
+    ArduousDisplayRegisters registers;
+};
+
+// Everything a frontend presents for one frame
+struct ArduousFrame {
+    std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> display;
+    // getBlendedFrameBuffer() when frame blending is on, otherwise getIntensityFrameBuffer()
+    std::vector<uint8_t> intensity;
+    std::vector<int16_t> audio;  // interleaved stereo
+    ArduousRgbLed rgbLed;
+    uint32_t leds;  // ARDUOUS_LED_* bits lit at the end of the frame
+    ArduousFrameStats stats;
+    // False when intensity is identical to the previous frame's, so the upload can be skipped
+    bool displayChanged;
+};
+
+// Per display RAM page, the columns whose byte changed
+using ArduousDirtyColumns = std::array<std::bitset<DISPLAY_WIDTH>, DISPLAY_HEIGHT / 8>;
+
+constexpr char GPIO_FIRST_PORT = 'B';
+constexpr char GPIO_LAST_PORT = 'F';
+constexpr int GPIO_PORT_COUNT = GPIO_LAST_PORT - GPIO_FIRST_PORT + 1;
+
 class Arduous {
     struct PinCallbackParamT {
         Arduous* self;
         int speakerPin;
     };
+    struct IoWriteHookT;
+    struct GpioCallbackParamT {
+        Arduous* self;
+        char port;
+        int pin;
+    };
 
    public:
+    // Returns the voltage on an ADC channel, in millivolts
+    using AdcInputHook = std::function<uint32_t(int channel)>;
+    // Receives non-fatal emulation warnings (unimplemented I/O, crashes, EEPROM abuse...)
+    using WarningCallback = std::function<void(const std::string& message)>;
+    // Called whenever a GPIO pin level changes, whether driven by the sketch or from outside
+    using PinChangeCallback = std::function<void(char port, int pin, bool value)>;
+    // Receives every emitted ArduousEvent as it happens
+    using EventCallback = std::function<void(const ArduousEvent& event)>;
+    // Called after the sketch writes an I/O register, addressed in data space (e.g. 0x25 for PORTB). oldValue is the
+    // register's content just before the write.
+    using IoWriteHook = std::function<void(uint16_t address, uint8_t oldValue, uint8_t newValue, uint64_t cycle)>;
+
     Arduous();
     Arduous(const Arduous&) = delete;
     Arduous(Arduous&&) = delete;
     Arduous& operator=(const Arduous&) = delete;
     Arduous& operator=(Arduous&&) = delete;
-    ~Arduous() = default;
+    ~Arduous();
 
-    void loadHexBuffer(const char *data, size_t sz);
+    ArduousLoadResult loadHexBuffer(const char* data, size_t sz);
+    // Load a flat flash image starting at address 0
+    ArduousLoadResult loadProgramBytes(const uint8_t* data, size_t size);
+    ArduousLoadResult loadProgramWords(const uint16_t* words, size_t count);
+    // Hashes of the program image as loaded, excluding any bootloader
+    uint32_t getProgramCrc32();
+    std::string getProgramSha256();
+    bool identifyProgram(RomDatabase& database, ArduousRomInfo& info);
+    // Load a different program and restart, keeping EEPROM, the FX chip and all configuration
+    ArduousLoadResult swapProgram(const uint8_t* data, size_t size);
+    ArduousLoadResult swapProgramHex(const char* buf, size_t size);
+    // Attaches an FX flash chip if there isn't one yet, and writes an image into it
+    bool loadFxImage(const uint8_t* data, size_t size, uint32_t address);
+    // Places a game's fxdata.bin at the end of the chip, the way the FX development tools upload it, followed by a
+    // 4K aligned save region if the game uses one
+    bool loadFxData(const uint8_t* data, size_t size, size_t saveSize = 0);
+    FxFlash* getFxFlash();
+    // Writes a whole flashcart image to the FX chip. With a bootloader loaded its menu runs and reflashes the
+    // MCU with the selected game; otherwise the first game in the cart is booted directly.
+    ArduousLoadResult loadFlashcart(const uint8_t* data, size_t size);
+    const std::vector<ArduousFlashcartSlot>& getFlashcartSlots();
+    // Boots a game straight from the cart in the FX chip, bypassing the bootloader menu
+    ArduousLoadResult bootFlashcartSlot(size_t index);
+    // The current game's FX save region, kept apart from the read-only cart data so it can be persisted
+    size_t getFxSaveSize();
+    bool isFxSaveDirty();
+    void getFxSave(uint8_t* data);
+    bool loadFxSave(const uint8_t* data, size_t size);
+    void setFxSaveDirtyCallback(std::function<void()> callback);
+    bool loadBootloaderHexBuffer(const char* data, size_t sz);
+    void setFuses(ArduousFuses newFuses);
+    // Selects button, LED, speaker, display and FX pins; applied when the next program is loaded
+    void setHardwareVariant(ArduousHardwareVariant variant);
+    void setDisplayController(ArduousDisplayController controller);
+    // Takes effect on the next init
+    void setDisplayInterface(ArduousDisplayInterface bus);
+    // Emulated CPU clock, e.g. 8 MHz for some homemade builds or 20 MHz overclocked; frames, audio and the FX
+    // chip's timings all follow it
+    void setClockFrequency(uint64_t hz);
+    // Plugs or unplugs USB power, which the program sees as the VBUS bit in USBSTA
+    void setUsbPowered(bool powered);
+    bool isUsbPowered();
+    uint64_t getClockFrequency();
     void init(uint8_t* boot, uint32_t bootSize, uint32_t bootBase);
+    // Like the reset button: registers and peripherals return to their reset state, SRAM, flash and EEPROM are kept
     void reset();
-    void emulateFrame();
+    void resetWithCause(ArduousResetCause cause);
+    // Removes and restores power: the whole device restarts with SRAM filled with the power-on pattern, keeping only
+    // flash, EEPROM and the FX chip
+    void powerCycle();
+    void setPowerOnRamPattern(uint8_t pattern);
+    // With a bootloader mapped, runs it at 16 times normal speed after power-on until it jumps to the application,
+    // so its wait window doesn't hold up the first frames. Later resets go through the bootloader at normal speed.
+    void setFastBoot(bool enabled);
+    // Runs freq / TIMING_FPS cycles, carrying the fractional cycle and any overshoot of the last instruction into
+    // the next frame
+    ArduousFrameStats emulateFrame();
+    void emulateFrame(ArduousFrame& frame);
     void update(int steps = 1);
     void setButtonState(ArduousButtonState newButtonState);
+    // ARDUOUS_BUTTON_* bits, set all at once
+    void setButtons(uint8_t buttons);
+    uint8_t getButtons();
+    // Presses or releases a button once the cpu reaches the given cycle, for sub-frame precise scripted input
+    void queueInput(uint64_t atCycle, ArduousButton button, bool pressed);
+    void clearQueuedInput();
+    uint64_t runForCycles(uint64_t cycles);
+    uint64_t runUntilCycle(uint64_t cycle);
+    // Runs until the program counter reaches a byte address in flash, or the cycle budget runs out
+    bool runUntilPc(uint32_t address, uint64_t maxCycles, uint64_t& elapsedCycles);
+    // Runs until the current function returns to its caller, or the cycle budget runs out
+    bool runUntilReturn(uint64_t maxCycles, uint64_t& elapsedCycles);
 
     std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> getVideoFrameBuffer();
+    // Per-pixel brightness 0-255, following the contrast register and going dark while the display is off
+    std::vector<uint8_t> getIntensityFrameBuffer();
+    // Display RAM in the controller's page-packed order, byte for byte comparable with Arduboy2's sBuffer
+    std::array<uint8_t, DISPLAY_WIDTH * DISPLAY_HEIGHT / 8> getDisplayPages();
+    // Display RAM bytes that changed during the last emulated frame, in getDisplayPages() order
+    const ArduousDirtyColumns& getDirtyColumns();
+    bool isDisplayDirty();
+    // Grayscale games flicker pixels across display updates; blending averages the last few updates so their gray
+    // levels come through. 0 or 1 turns it off.
+    void setFrameBlending(unsigned int updates);
+    std::vector<uint8_t> getBlendedFrameBuffer();
+    // OLED pixel response: each frame, pixels move toward their new brightness with these time constants.
+    // Zero for both turns it off.
+    void setPixelPersistence(float attackSeconds, float decaySeconds);
+    // Brightness 0.0-1.0 after persistence, updated at the end of each frame
+    std::vector<float> getPersistenceFrameBuffer();
     std::vector<int16_t> getAudioBuffer();
+    // The pin stream is resampled to this rate, e.g. 32000, 44100 or 48000
+    void setAudioSampleRate(unsigned int rate);
+    unsigned int getAudioSampleRate();
+    void setAudioSynthesis(ArduousAudioSynthesis synthesis);
+    void setAudioChannels(ArduousAudioChannels channels);
+    void setPixelChangeTracking(bool enabled);
+    std::vector<ArduousPixelRun> getChangedPixelRuns();
+    // Watches added here are sampled with the frame number at the end of every emulated frame
+    WatchList& getWatchList();
+    // Lets watch expressions name the game's variables, e.g. [playerX]:s16, from the .elf it was built from; returns
+    // false if the file has no data symbols
+    bool loadWatchSymbols(const std::vector<uint8_t>& elf);
+    // A display update is detected once this many data bytes reach the display without the write cursor being moved
+    // by 0x21 or 0x22; 1024 is one full frame buffer
+    void setDisplayUpdateBytes(unsigned int bytes);
+    const ArduousDisplayRegisters& getDisplayRegisters();
+    ArduousDisplayState getDisplayState();
+    uint64_t getDisplayUpdateCount();
+    // Display updates during the last emulated second, i.e. the game's own frame rate
+    unsigned int getDisplayFps();
+    // How often the panel itself scans out, from the controller's clock settings; Arduboy2's init gives about 125 Hz
+    float getDisplayRefreshRate();
+    ArduousRgbLed getRgbLed();
+    // ARDUOUS_LED_* bits of the LEDs lit right now, including the TX and RX activity LEDs
+    uint32_t getLitLeds();
+    std::vector<uint8_t> takeSerialOutput();
+    void sendSerialInput(const uint8_t* data, size_t size);
+    // Cross-connects the USART1 lines of two instances like a link cable. Emulate both with the same frame steps: a
+    // byte arrives at the same point of the receiver's next emulated frame as it was sent in the sender's, so bytes
+    // keep their spacing, but the instance emulated first each frame gets its peer's bytes one frame late
+    void linkSerial(Arduous& other);
+    void unlinkSerial();
+    void setAdcInputHook(AdcInputHook hook);
+    void setWarningCallback(WarningCallback callback);
+    void setLogLevel(ArduousLogSubsystem subsystem, ArduousLogLevel level);
+    void setLogSink(ArduousLogSink sink);
+    void setPinChangeCallback(PinChangeCallback callback);
+    // Returns false for addresses outside I/O space (0x20 up to the end of the extended I/O registers)
+    bool addIoWriteHook(uint16_t address, IoWriteHook hook);
+    void addEventCallback(EventCallback callback);
+    // Queued events are kept until taken, so only enable queueing if you drain it regularly
+    void setEventQueueEnabled(bool enabled);
+    std::vector<ArduousEvent> takeEvents();
+    // Per-instruction debug aids, off by default for full speed: stack pointer fault detection and the PC history
+    // shown in crash reports
+    void setDebugChecks(bool enabled);
+    void setPcHistorySize(size_t entries);
+    // Oldest first
+    std::vector<ArduousPcHistoryEntry> getPcHistory();
+    // Empty unless the CPU has crashed
+    std::string getCrashReport();
+    // Audit mode counts each distinct emulation problem per PC, logging only its first occurrence
+    void setAuditMode(bool enabled);
+    std::vector<ArduousAuditEntry> getAuditReport();
+    void clearIoWriteHooks();
     size_t getSaveSize();
     bool save(void* data, size_t size);
     bool load(const void* data, size_t size);
 
     size_t getRamSize();
     void *getRam();
+    // 0 and null until a program is loaded
     size_t getEEPROMSize();
     void *getEEPROM();
+    // Set when the program writes EEPROM, so frontends only persist it after a change
+    bool isEEPROMDirty();
+    void setEEPROMDirtyCallback(std::function<void()> callback);
+    // Both clear the dirty flag; loading fails if the size doesn't match the part's EEPROM. Contents loaded before
+    // a program are kept until the device is created.
+    void saveEEPROM(uint8_t* data);
+    bool loadEEPROM(const uint8_t* data, size_t size);
 
    private:
     // Atcore cpu;
-    avr_t* cpu;
+    avr_t* cpu = nullptr;
     // SSD1306 screen;
     ssd1306_t screen;
+    ArduousPinMapping pins = getPinMapping(ArduousHardwareVariant::Arduboy);
+    ArduousDisplayController displayController = ArduousDisplayController::Ssd1306;
+    ArduousDisplayInterface displayInterface = ArduousDisplayInterface::Spi;
+    bool displayTwiSelected = false;
+    bool displayTwiControlPending = false;
+    bool displayTwiSingleByte = false;
+    bool displayTwiData = false;
+    unsigned int displayUpdateBytes = DISPLAY_WIDTH * DISPLAY_HEIGHT / 8;
+    DisplayCommandParser displayCommands;
+    unsigned int frameBlending = 0;
+    std::deque<std::vector<uint8_t>> blendFrames;
+    bool blendSamplePending = false;
+    float persistenceAttack = 0;
+    float persistenceDecay = 0;
+    std::vector<float> persistence;
+    uint64_t displayUpdateCount = 0;
+    unsigned int displayUpdatesThisSecond = 0;
+    unsigned int displayFps = 0;
+    std::unique_ptr<FxFlash> fxFlash;
+    std::vector<ArduousFlashcartSlot> flashcartSlots;
 
     std::string mmcu = "atmega32u4";
     uint64_t freq = 16000000;
+    ArduousFuses fuses = {};
+    std::vector<uint8_t> program;
+    uint32_t programBase = 0;
+    uint8_t powerOnRamPattern = 0x00;
+    std::vector<uint8_t> bootloader;
+    uint32_t bootloaderBase = 0;
+    uint32_t bootSectionStart = 0;
+    bool fastBoot = false;
+    bool fastBootPending = false;
+    uint64_t fastBootLimit = 0;
     uint64_t cyclesPerVideoFrame;
-    uint64_t cyclesPerAudioSample;
-    unsigned int audioSamplesPerVideoFrame;
-    uint64_t frameStartCycle;
-    uint64_t frameEndCycle;
+    unsigned int audioSampleRate = TIMING_SAMPLE_RATE;
+    // Sample rates that don't divide evenly into frames carry the remainder over to the next frame
+    unsigned int audioSampleRemainder = 0;
+    unsigned int audioSamplesThisFrame = TIMING_SAMPLE_RATE / TIMING_FPS;
+    ArduousAudioSynthesis audioSynthesis = ArduousAudioSynthesis::PointSampled;
+    ArduousAudioChannels audioChannels = ArduousAudioChannels::Differential;
+    // Band-limited synthesis: left and right levels integrated over the sample being built
+    std::array<int64_t, 2> audioAccumulator = {0, 0};
+    uint64_t audioAccumulatedUntil = 0;
+    uint64_t frameStartCycle = 0;
+    uint64_t frameEndCycle = 0;
+    unsigned int frameCycleRemainder = 0;
 
     ArduousButtonState buttonState = {};
+    std::multimap<uint64_t, std::pair<ArduousButton, bool>> inputQueue;
     std::array<PinCallbackParamT, 2> pinCallbackParamTs;
+    std::array<GpioCallbackParamT, GPIO_PORT_COUNT * 8> gpioCallbackParamTs;
+    PinChangeCallback pinChangeCallback;
+    std::vector<std::unique_ptr<IoWriteHookT>> ioWriteHooks;
+    std::vector<EventCallback> eventCallbacks;
+    bool eventQueueEnabled = false;
+    std::vector<ArduousEvent> eventQueue;
+    uint32_t litLeds = 0;
+    // Cycles each RGB channel has been lit so far this frame
+    std::array<uint64_t, 3> ledLitCycles = {0, 0, 0};
+    uint64_t ledIntegratedUntil = 0;
+    ArduousRgbLed rgbLed = {0, 0, 0};
+    bool stackFault = false;
+    std::vector<ArduousPcHistoryEntry> pcHistory = std::vector<ArduousPcHistoryEntry>(64);
+    size_t pcHistoryNext = 0;
+    size_t pcHistoryCount = 0;
+    bool debugChecks = false;
+    // Set while anything needs checking around each instruction, so step() is otherwise just avr_run
+    bool slowPath = false;
+    std::string crashReport;
+    bool auditMode = false;
+    std::map<std::pair<const void*, uint32_t>, ArduousAuditEntry> auditEntries;
 
     std::bitset<2> speakerPins;
     std::vector<int16_t> audioBuffer;
 
-    int16_t getCurrentSpeakerSample();
+    bool pixelChangeTracking = false;
+    std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> previousVideoFrameBuffer;
+    std::vector<ArduousPixelRun> changedPixelRuns;
+    WatchList watchList;
+    std::array<uint8_t, DISPLAY_WIDTH * DISPLAY_HEIGHT / 8> previousDisplayPages{};
+    ArduousDirtyColumns dirtyColumns;
+    std::vector<uint8_t> previousIntensity;
+
+    std::vector<uint8_t> serialOutput;
+    std::deque<uint8_t> serialInput;
+    Arduous* serialPeer = nullptr;
+    // Bytes from the linked instance, with the cycle each one arrives at
+    std::deque<std::pair<uint64_t, uint8_t>> linkedSerialInput;
+    bool serialInputReady = true;
+
+    AdcInputHook adcInputHook;
+    uint32_t adcNoiseState = 0x2545F491;
+
+    WarningCallback warningCallback;
+    std::array<ArduousLogLevel, static_cast<size_t>(ArduousLogSubsystem::Count)> logLevels;
+    ArduousLogSink logSink;
+    int lastCpuState = cpu_Running;
+    uint64_t frameCount = 0;
+    unsigned int eepromWritesThisSecond = 0;
+    bool eepromDirty = false;
+    bool eepromMasterEnabled = false;
+    uint64_t eepromMasterEnableCycle = 0;
+    bool toneActive = false;
+    uint64_t lastSpeakerChangeCycle = 0;
+    bool inBootloader = false;
+    bool usbPowered = false;
+    std::vector<uint8_t> pendingEeprom;
+    // Callbacks seen in the cycle timer pool, with the index of the io module each was registered for or -1 for none.
+    // Save states refer to timers by their index here, so a state can only schedule what this mcu has itself.
+    std::vector<std::pair<avr_cycle_timer_t, int>> cycleTimerSources;
+    std::function<void()> eepromDirtyCallback;
+
+    bool step();
+    uint16_t getStackPointer();
+    void checkStackPointer();
+    uint8_t getStatusRegister();
+    void recordPcHistory();
+    void buildCrashReport();
+    bool recordAudit(const void* kind, const std::string& message);
+    void registerIoWriteHook(IoWriteHookT* hook);
+    void emitEvent(ArduousEventType type, uint32_t value);
+    void applyQueuedInput();
+    void updateSlowPath();
+    bool getPinLevel(ArduousPin pin);
+    bool isLedLit(ArduousPin pin);
+    void updateLeds();
+    void integrateLeds();
+    void updatePersistence(const std::vector<uint8_t>& intensity, float seconds);
+    std::array<int16_t, 2> getCurrentSpeakerSample();
     void extendAudioBuffer();
+    uint64_t getAudioSampleCycle(unsigned int sample);
+    void updateChangedPixelRuns();
+    void updateDirtyColumns();
+    void setInputPin(ArduousPin pin, bool value);
+    void attachFxFlash();
+    ArduousLoadResult keepingEEPROM(std::function<ArduousLoadResult()> load);
+    ArduousLoadResult loadProgram(uint8_t* program, uint32_t base, uint32_t size);
+    uint32_t getBootSectionSize();
+    uint32_t getBootSectionStart();
+    void pumpSerialInput();
+    void queueLinkedSerialInput(uint64_t frameCycle, uint8_t byte);
+    void applyLinkedSerialInput();
+    uint32_t readAdcInput(int channel);
+    bool isLogging(ArduousLogSubsystem subsystem, ArduousLogLevel level);
+    void log(ArduousLogSubsystem subsystem, ArduousLogLevel level, const char* format, ...);
+    ArduousLogFunction logFunction(ArduousLogSubsystem subsystem);
+    void warn(ArduousLogSubsystem subsystem, const std::string& message);
+    void checkFrameWarnings();
+    void destroyCpu();
+    void fastForwardBootloader();
+    void saveInterrupts(uint8_t*& buffer);
+    void loadInterrupts(const uint8_t*& buffer);
+    int getIoModuleIndex(const void* param);
+    struct avr_io_t* getIoModule(int index);
+    void learnCycleTimerSources();
+    void saveCycleTimers(uint8_t*& buffer);
+    bool checkCycleTimers(const uint8_t* buffer);
+    void loadCycleTimers(const uint8_t* buffer);
+    size_t getPeripheralStateSize();
+    void savePeripheralState(uint8_t*& buffer);
+    bool checkPeripheralState(const uint8_t* buffer);
+    void loadPeripheralState(const uint8_t* buffer);
+    void saveFrameState(uint8_t*& buffer);
+    void loadFrameState(const uint8_t*& buffer);
+    void displayByte(bool data, uint8_t byte);
 
     static void soundPinCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void serialOutputCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void serialXonCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void serialXoffCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void adcTriggerCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void ioWriteCallback(avr_t* avr, avr_io_addr_t address, uint8_t value, void* param);
+    static void gpioPinCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void displaySpiCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void displayTwiCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void eepromControlCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void simavrLogger(avr_t* avr, const int level, const char* format, va_list ap);
 };
 
 #endif
@@ -0,0 +1,36 @@
+#ifndef ARDUOUS_BOOKMARKS_H
+#define ARDUOUS_BOOKMARKS_H
+
+#include <cstdint>
+#include <ctime>
+#include <string>
+#include <vector>
+
+#include "arduous/arduous.h"
+
+struct ArduousBookmark {
+    std::string name;
+    std::string note;
+    std::time_t createdAt;
+    std::vector<uint8_t> state;
+};
+
+// Named savestates with notes, for routing and long debugging sessions
+class Bookmarks {
+   public:
+    explicit Bookmarks(Arduous& arduous);
+
+    // Returns false, keeping any earlier bookmark of the same name, if the state can't be saved
+    bool add(const std::string& name, const std::string& note = "");
+    bool jumpTo(const std::string& name);
+    bool remove(const std::string& name);
+    const std::vector<ArduousBookmark>& list() const;
+
+   private:
+    Arduous& arduous;
+    std::vector<ArduousBookmark> bookmarks;
+
+    std::vector<ArduousBookmark>::iterator find(const std::string& name);
+};
+
+#endif
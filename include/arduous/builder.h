@@ -0,0 +1,47 @@
+#ifndef ARDUOUS_BUILDER_H
+#define ARDUOUS_BUILDER_H
+
+#include <cstdint>
+#include <memory>
+#include <vector>
+
+#include "arduous/arduous.h"
+
+// Collects configuration for a new Arduous, e.g.
+// ArduousBuilder().variant(ArduousHardwareVariant::Homemade).display(ArduousDisplayController::Sh1106).build()
+class ArduousBuilder {
+   public:
+    ArduousBuilder& variant(ArduousHardwareVariant variant);
+    ArduousBuilder& display(ArduousDisplayController controller);
+    ArduousBuilder& displayInterface(ArduousDisplayInterface bus);
+    ArduousBuilder& clockHz(uint64_t hz);
+    ArduousBuilder& fuses(ArduousFuses fuses);
+    ArduousBuilder& eeprom(std::vector<uint8_t> contents);
+    ArduousBuilder& fxData(std::vector<uint8_t> data, size_t saveSize = 0);
+    ArduousBuilder& audioSampleRate(unsigned int rate);
+    ArduousBuilder& audioSynthesis(ArduousAudioSynthesis synthesis);
+    ArduousBuilder& audioChannels(ArduousAudioChannels channels);
+    // Strict mode turns on audit mode, collecting every distinct emulation problem by PC
+    ArduousBuilder& strict(bool enabled);
+    ArduousBuilder& fastBoot(bool enabled);
+    ArduousBuilder& debugChecks(bool enabled);
+    std::unique_ptr<Arduous> build();
+
+   private:
+    ArduousHardwareVariant hardwareVariant = ArduousHardwareVariant::Arduboy;
+    ArduousDisplayController displayController = ArduousDisplayController::Ssd1306;
+    ArduousDisplayInterface displayBus = ArduousDisplayInterface::Spi;
+    uint64_t clock = 16000000;
+    ArduousFuses fuseBytes;
+    std::vector<uint8_t> eepromContents;
+    std::vector<uint8_t> fxContents;
+    size_t fxSaveSize = 0;
+    unsigned int sampleRate = TIMING_SAMPLE_RATE;
+    ArduousAudioSynthesis synthesis = ArduousAudioSynthesis::PointSampled;
+    ArduousAudioChannels channels = ArduousAudioChannels::Differential;
+    bool strictMode = false;
+    bool fastBootEnabled = false;
+    bool debugChecksEnabled = false;
+};
+
+#endif
@@ -0,0 +1,70 @@
+#ifndef ARDUOUS_DISPLAY_COMMANDS_H
+#define ARDUOUS_DISPLAY_COMMANDS_H
+
+#include <cstdint>
+
+constexpr unsigned int DISPLAY_WIDTH = 128;
+constexpr unsigned int DISPLAY_HEIGHT = 64;
+
+// Display controller settings that simavr's SSD1306 model accepts but doesn't keep, tracked from the command stream
+struct ArduousDisplayRegisters {
+    uint8_t startLine = 0;
+    uint8_t displayOffset = 0;
+    // Number of COM lines driven; the panel rows above them stay dark
+    uint8_t multiplexRatio = 64;
+    // Set by 0x8D 0x14; tracked for diagnostics only, the output isn't blanked while it's off
+    bool chargePump = false;
+    // Oscillator setting in the high nibble, divide ratio minus one in the low nibble
+    uint8_t clockDivide = 0x80;
+    // Phase 2 in the high nibble, phase 1 in the low nibble, both in display clocks
+    uint8_t preCharge = 0x22;
+    // Window set by 0x21 and 0x22 for horizontal and vertical addressing
+    uint8_t columnStart = 0;
+    uint8_t columnEnd = 127;
+    uint8_t pageStart = 0;
+    uint8_t pageEnd = 7;
+    // 0xA5 lights every pixel regardless of display RAM
+    bool entireDisplayOn = false;
+};
+
+// Orientation settings simavr's SSD1306 model keeps as flags. Arduboy2 maps column 127 to SEG0 (0xA1) and scans COM
+// remapped (0xC8), which is the orientation the panel is read in; the opposite settings mirror or flip it.
+struct ArduousDisplayScan {
+    // 0xA0
+    bool segmentRemap0 = false;
+    // 0xC0
+    bool comScanNormal = false;
+    // SH1106 programs write pages with the column address starting at 2
+    unsigned int columnOffset = 0;
+};
+
+// Panel refresh rate in Hz implied by the clock, pre-charge and multiplex settings
+float getDisplayRefreshRate(const ArduousDisplayRegisters& registers);
+// Finds the display RAM pixel shown at panel position (x, y). Returns false for rows that aren't driven, which stay
+// dark.
+bool getDisplayRamPixel(const ArduousDisplayRegisters& registers, const ArduousDisplayScan& scan, unsigned int x,
+                        unsigned int y, unsigned int& column, unsigned int& row);
+
+class DisplayCommandParser {
+   public:
+    void reset();
+    void command(uint8_t byte);
+    // Counts a byte written to display RAM and returns true each time a full frame of frameBytes has been written.
+    // Only the cursor commands of horizontal and vertical addressing (0x21, 0x22) restart the count. Page addressing,
+    // the only mode on the SH1106, sets the page and column before every page, and those commands leave it alone.
+    bool data(unsigned int frameBytes);
+    const ArduousDisplayRegisters& getRegisters() const;
+    // Restores saved registers and drops any half-received command
+    void setRegisters(const ArduousDisplayRegisters& saved);
+
+   private:
+    ArduousDisplayRegisters registers;
+    uint8_t pendingCommand = 0;
+    unsigned int argumentsLeft = 0;
+    unsigned int argumentIndex = 0;
+    unsigned int dataBytes = 0;
+
+    void argument(uint8_t byte);
+};
+
+#endif
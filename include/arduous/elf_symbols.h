@@ -0,0 +1,16 @@
+#ifndef ARDUOUS_ELF_SYMBOLS_H
+#define ARDUOUS_ELF_SYMBOLS_H
+
+#include <cstddef>
+#include <cstdint>
+#include <map>
+#include <string>
+
+// AVR-GCC links data space at this offset so its addresses don't collide with flash
+constexpr uint32_t ELF_DATA_SPACE_OFFSET = 0x800000;
+
+// Reads the variables from an AVR ELF file's symbol table, by name, as data space addresses. Returns an empty map
+// for files that aren't 32-bit little-endian ELF or have no symbol table.
+std::map<std::string, uint32_t> readElfDataSymbols(const uint8_t* data, size_t size);
+
+#endif
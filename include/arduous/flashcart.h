@@ -0,0 +1,51 @@
+#ifndef ARDUOUS_FLASHCART_H
+#define ARDUOUS_FLASHCART_H
+
+#include <cstddef>
+#include <cstdint>
+#include <string>
+#include <vector>
+
+constexpr size_t FLASHCART_HEADER_SIZE = 256;
+constexpr size_t FLASHCART_PROGRAM_UNIT = 128;
+constexpr uint16_t FLASHCART_NO_PAGE = 0xFFFF;
+// Title screens are a full 128x64 frame in the display's page layout
+constexpr size_t FLASHCART_TITLE_SIZE = 1024;
+
+// One slot of an FX flashcart image; pages are 256 byte FX flash pages
+struct ArduousFlashcartSlot {
+    uint8_t category = 0;
+    uint16_t page = 0;
+    uint16_t slotPages = 0;
+    size_t programSize = 0;
+    uint16_t programPage = FLASHCART_NO_PAGE;
+    uint16_t dataPage = FLASHCART_NO_PAGE;
+    uint16_t savePage = FLASHCART_NO_PAGE;
+    std::string title;
+    std::string version;
+    std::string developer;
+    std::string info;
+};
+
+// A game or category header to be written by buildFlashcart(); entries without a program are category headers
+struct ArduousFlashcartEntry {
+    uint8_t category = 0;
+    std::string hex;
+    std::vector<uint8_t> titleImage;
+    std::vector<uint8_t> fxData;
+    size_t saveSize = 0;
+    std::string title;
+    std::string version;
+    std::string developer;
+    std::string info;
+};
+
+enum class ArduousFlashcartBuildResult { Ok, InvalidHex, InvalidTitleImage, TooLarge };
+
+bool isFlashcartImage(const uint8_t* data, size_t size);
+// Walks the slots from the start of the image, stopping at the first page without a header
+std::vector<ArduousFlashcartSlot> parseFlashcart(const uint8_t* data, size_t size);
+ArduousFlashcartBuildResult buildFlashcart(const std::vector<ArduousFlashcartEntry>& entries,
+                                           std::vector<uint8_t>& image);
+
+#endif
@@ -0,0 +1,79 @@
+#ifndef ARDUOUS_FX_FLASH_H
+#define ARDUOUS_FX_FLASH_H
+
+#include <cstddef>
+#include <cstdint>
+#include <functional>
+#include <vector>
+
+#include "arduous/log.h"
+#include "sim_avr.h"
+
+constexpr size_t FX_FLASH_SIZE = 16 * 1024 * 1024;
+constexpr size_t FX_FLASH_PAGE_SIZE = 256;
+constexpr size_t FX_FLASH_SECTOR_SIZE = 4096;
+// Chip select, command phase, address, write enable, power down and busy time
+constexpr size_t FX_FLASH_STATE_SIZE = 1 + 1 + 4 + 4 + 1 + 1 + 8;
+
+// W25Q128 SPI NOR flash as fitted to the Arduboy FX, sharing the SPI bus with the display
+class FxFlash {
+   public:
+    FxFlash();
+    FxFlash(const FxFlash&) = delete;
+    FxFlash(FxFlash&&) = delete;
+    FxFlash& operator=(const FxFlash&) = delete;
+    FxFlash& operator=(FxFlash&&) = delete;
+    ~FxFlash() = default;
+
+    void connect(avr_t* avr, char csPort, int csPin);
+    bool load(const uint8_t* image, size_t size, uint32_t address);
+    uint8_t* getData();
+
+    // Sectors modified by the program since they were last cleared, e.g. by a game saving
+    bool isSectorDirty(uint32_t sector);
+    void clearDirtySectors();
+    void setSaveRegion(uint32_t address, uint32_t size);
+    uint32_t getSaveAddress();
+    uint32_t getSaveSize();
+    bool isSaveDirty();
+    // Called the first time the save region is modified after the dirty sectors were cleared
+    void setSaveDirtyCallback(std::function<void()> callback);
+    // Reports unsupported commands, and commands the chip ignores while busy, asleep or write protected
+    void setLog(ArduousLogFunction function);
+    // The chip's own state for save states, FX_FLASH_STATE_SIZE bytes; the flash contents aren't included
+    void saveState(uint8_t*& buffer);
+    void loadState(const uint8_t*& buffer);
+
+   private:
+    avr_t* avr = nullptr;
+    std::vector<uint8_t> data;
+
+    bool selected = false;
+    uint8_t command = 0;
+    unsigned int byteIndex = 0;
+    uint32_t address = 0;
+    bool writeEnabled = false;
+    bool poweredDown = false;
+    avr_cycle_count_t busyUntil = 0;
+
+    std::vector<bool> dirtySectors;
+    uint32_t saveAddress = 0;
+    uint32_t saveSize = 0;
+    bool saveDirty = false;
+    std::function<void()> saveDirtyCallback;
+    ArduousLogFunction log;
+
+    bool isBusy();
+    uint8_t getStatus();
+    void setBusy(unsigned int microseconds);
+    uint8_t transfer(uint8_t in);
+    void finishCommand();
+    void erase(uint32_t start, uint32_t size);
+    void markDirty(uint32_t start, uint32_t size);
+    void report(ArduousLogLevel level, const char* format, uint8_t value);
+
+    static void spiCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+    static void chipSelectCallback(struct avr_irq_t* irq, uint32_t value, void* param);
+};
+
+#endif
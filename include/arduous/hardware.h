@@ -0,0 +1,47 @@
+#ifndef ARDUOUS_HARDWARE_H
+#define ARDUOUS_HARDWARE_H
+
+#include <cstdint>
+
+enum class ArduousHardwareVariant { Arduboy, DevKit, ArduboyMini, Homemade };
+
+// SH1106 panels map their 132 column RAM onto the glass starting at column 2
+enum class ArduousDisplayController { Ssd1306, Sh1106 };
+constexpr int SH1106_COLUMN_OFFSET = 2;
+
+// Homemade builds often wire the display to the TWI pins instead of SPI, at the usual 0x3C address
+enum class ArduousDisplayInterface { Spi, I2c };
+constexpr uint8_t DISPLAY_I2C_ADDRESS = 0x3C;
+
+// A port pin; port 0 means the signal isn't wired on this variant
+struct ArduousPin {
+    char port;
+    uint8_t pin;
+};
+
+struct ArduousPinMapping {
+    ArduousPin buttonUp;
+    ArduousPin buttonDown;
+    ArduousPin buttonLeft;
+    ArduousPin buttonRight;
+    ArduousPin buttonA;
+    ArduousPin buttonB;
+    ArduousPin ledRed;
+    ArduousPin ledGreen;
+    ArduousPin ledBlue;
+    ArduousPin ledTx;
+    ArduousPin ledRx;
+    ArduousPin speaker1;
+    ArduousPin speaker2;
+    ArduousPin displayChipSelect;
+    ArduousPin displayDataCommand;
+    ArduousPin displayReset;
+    ArduousPin fxChipSelect;
+};
+
+constexpr ArduousPin PIN_NONE = {0, 0};
+
+// All variants run at 16 MHz; homemade builds may pair any of them with an SH1106
+ArduousPinMapping getPinMapping(ArduousHardwareVariant variant);
+
+#endif
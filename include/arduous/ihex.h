@@ -0,0 +1,14 @@
+#ifndef ARDUOUS_IHEX_H
+#define ARDUOUS_IHEX_H
+
+#include <cstddef>
+#include <cstdint>
+
+#include "arduous/log.h"
+
+// Returns the first contiguous chunk of an Intel HEX image, to be released with free()
+// Parse problems are reported through log, if given.
+uint8_t* read_ihex_buffer(const char* data, size_t sz, uint32_t* dsize, uint32_t* start,
+                          const ArduousLogFunction& log = nullptr);
+
+#endif
@@ -0,0 +1,19 @@
+#ifndef ARDUOUS_LOG_H
+#define ARDUOUS_LOG_H
+
+#include <functional>
+#include <string>
+
+enum class ArduousLogSubsystem { Cpu, Display, Audio, Eeprom, Serial, Fx, Count };
+
+enum class ArduousLogLevel { None, Error, Warning, Info, Debug, Trace };
+
+using ArduousLogSink =
+    std::function<void(ArduousLogSubsystem subsystem, ArduousLogLevel level, const std::string& message)>;
+
+// Lets components outside Arduous report through its level filter and sink, already bound to a subsystem
+using ArduousLogFunction = std::function<void(ArduousLogLevel level, const std::string& message)>;
+
+const char* getLogSubsystemName(ArduousLogSubsystem subsystem);
+
+#endif
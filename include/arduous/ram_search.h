@@ -0,0 +1,32 @@
+#ifndef ARDUOUS_RAM_SEARCH_H
+#define ARDUOUS_RAM_SEARCH_H
+
+#include <cstddef>
+#include <cstdint>
+#include <vector>
+
+enum class RamSearchValueType { U8, S8, U16, S16 };
+
+enum class RamSearchComparison { Changed, Unchanged, Increased, Decreased, EqualTo };
+
+// Narrows down RAM addresses holding a value (health, score...) across successive snapshots,
+// the way cheat searches in other emulators work. Multi-byte values are little-endian, like AVR-GCC's.
+class RamSearch {
+   public:
+    explicit RamSearch(RamSearchValueType valueType = RamSearchValueType::U8);
+
+    void start(const uint8_t* ram, size_t size);
+    void filter(const uint8_t* ram, size_t size, RamSearchComparison comparison, int32_t value = 0);
+
+    const std::vector<uint32_t>& getCandidates() const;
+    int32_t readValue(const uint8_t* ram, uint32_t address) const;
+
+   private:
+    RamSearchValueType valueType;
+    std::vector<uint8_t> previous;
+    std::vector<uint32_t> candidates;
+
+    size_t getValueSize() const;
+};
+
+#endif
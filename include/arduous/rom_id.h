@@ -0,0 +1,25 @@
+#ifndef ARDUOUS_ROM_ID_H
+#define ARDUOUS_ROM_ID_H
+
+#include <cstddef>
+#include <cstdint>
+#include <string>
+
+uint32_t getCrc32(const uint8_t* data, size_t size);
+// Lower-case hex digest
+std::string getSha256(const uint8_t* data, size_t size);
+
+struct ArduousRomInfo {
+    std::string title;
+    // Quirks a frontend may want to apply for this game
+    bool grayscale = false;
+};
+
+// Implemented by frontends to map known programs to titles and per-game quirks
+class RomDatabase {
+   public:
+    virtual ~RomDatabase() = default;
+    virtual bool lookup(uint32_t crc32, const std::string& sha256, ArduousRomInfo& info) = 0;
+};
+
+#endif
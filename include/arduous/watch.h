@@ -0,0 +1,59 @@
+#ifndef ARDUOUS_WATCH_H
+#define ARDUOUS_WATCH_H
+
+#include <cstddef>
+#include <cstdint>
+#include <map>
+#include <string>
+#include <vector>
+
+// ATmega32u4 data space: registers, I/O and 2.5K of SRAM
+constexpr size_t WATCH_MEMORY_SIZE = 0xB00;
+// Samples kept by default: five minutes at 60 frames per second
+constexpr size_t WATCH_DEFAULT_DEPTH = 5 * 60 * 60;
+
+// Records RAM variables every frame for plotting; Arduous::getWatchList() is sampled after each frame, other
+// lists need sample() called once per frame. Expressions look like "[0x0123]:u16" or "[playerX]:u16", where the type
+// is one of u8/s8/u16/s16/u32/s32 (default u8) and values are read little-endian, like AVR-GCC stores them.
+class WatchList {
+   public:
+    explicit WatchList(size_t memorySize = WATCH_MEMORY_SIZE);
+
+    // Names in expressions are looked up here when the watch is added, e.g. from readElfDataSymbols()
+    void setSymbols(std::map<std::string, uint32_t> symbols);
+    // Returns false for malformed expressions, unknown names and variables that don't fit in data space
+    bool add(const std::string& expression);
+    void clear();
+    bool empty() const;
+    // Only the newest samples are kept, overwriting the oldest. Changing the depth drops the recorded samples.
+    void setDepth(size_t frames);
+
+    void sample(uint64_t frame, const uint8_t* ram, size_t size);
+    std::string toCsv() const;
+    std::string toJson() const;
+
+   private:
+    struct Watch {
+        std::string expression;
+        uint32_t address;
+        size_t size;
+        bool isSigned;
+    };
+    struct Sample {
+        uint64_t frame;
+        std::vector<int64_t> values;
+    };
+
+    size_t memorySize;
+    size_t depth = WATCH_DEFAULT_DEPTH;
+    std::map<std::string, uint32_t> symbols;
+    std::vector<Watch> watches;
+    // Ring buffer of up to depth samples, oldest at oldestSample once full
+    std::vector<Sample> samples;
+    size_t oldestSample = 0;
+
+    void clearSamples();
+    const Sample& getSample(size_t index) const;
+};
+
+#endif
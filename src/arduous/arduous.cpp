@@ -1,289 +1,1482 @@
 #include "arduous/arduous.h"
 
 #include <algorithm>
+#include <cmath>
+#include <cstdarg>
 #include <cstdint>
 #include <cstdio>
+#include <cstdlib>
 #include <cstring>
 #include <functional>
+#include <iomanip>
 #include <iostream>
+#include <map>
+#include <sstream>
 #include <stdexcept>
 #include <string>
+#include <utility>
 
-#include "avr_ioport.h"
+#include "arduous/elf_symbols.h"
+#include "arduous/ihex.h"
+#include "avr_adc.h"
 #include "avr_eeprom.h"
+#include "avr_ioport.h"
+#include "avr_spi.h"
+#include "avr_timer.h"
+#include "avr_twi.h"
+#include "avr_uart.h"
+#include "avr_usb.h"
 #include "sim_avr.h"
 #include "sim_elf.h"
 #include "sim_hex.h"
+#include "sim_io.h"
+
+#define FUSE_HIGH_BOOTRST 0x01
+#define FUSE_HIGH_BOOTSZ 0x06
+
+#define MCUSR 0x54
+#define MCUSR_PORF 0
+#define MCUSR_EXTRF 1
+#define MCUSR_BORF 2
+#define MCUSR_WDRF 3
+
+#define SPL 0x5d
+#define SPH 0x5e
+#define IO_START 0x20
+#define SRAM_START 0x100
+
+#define EECR 0x3f
+#define EECR_EEPE 1
+#define EECR_EEMPE 2
+#define EEDR 0x40
+#define EEARL 0x41
+#define EEARH 0x42
+
+// Sustained EEPROM writes above this rate (per emulated second) wear out real hardware
+constexpr unsigned int EEPROM_WRITE_STORM_THRESHOLD = 64;
+constexpr uint64_t EEPROM_MASTER_ENABLE_CYCLES = 4;
+// Warn if a game hasn't switched the display on after this many frames
+constexpr uint64_t DISPLAY_ON_TIMEOUT_FRAMES = 5 * TIMING_FPS;
+// Brightness of a lit pixel at contrast 0
+constexpr unsigned int CONTRAST_MIN_INTENSITY = 16;
+// Speaker pins still for longer than a 20 Hz half-period mean the tone has ended
+constexpr unsigned int TONE_SILENCE_DIVISOR = 40;
+constexpr uint64_t FAST_BOOT_TIMEOUT_SECONDS = 10;
+// Frames' worth of bootloader run per emulated frame while fast booting, so no single retro_run stalls
+constexpr uint64_t FAST_BOOT_FRAMES_PER_FRAME = 16;
+// Save states start with "ARDS" and a layout version, bumped whenever the fields below change
+constexpr uint32_t SAVE_STATE_MAGIC = 0x53445241;
+constexpr uint32_t SAVE_STATE_VERSION = 4;
+// simavr's interrupt tables hold at most this many vectors
+constexpr size_t SAVE_STATE_MAX_VECTORS = 64;
+// Cycle timers are only saved for these io modules, or with no parameter at all
+static const char* const SAVE_STATE_TIMER_MODULES[] = {"port", "timer", "uart", "spi", "eeprom",
+                                                       "watchdog", "adc", "twi", "usb"};
+// A timer's source index and the cycles it has left
+constexpr size_t SAVE_STATE_CYCLE_TIMER_SIZE = sizeof(int16_t) + sizeof(int64_t);
+constexpr size_t SAVE_STATE_MAX_TIMER_SOURCES = 256;
+// A timer module's overflow schedule, top and compare cycles
+constexpr size_t SAVE_STATE_TIMER_SIZE = sizeof(avr_cycle_count_t) * (4 + AVR_TIMER_COMP_COUNT) + sizeof(uint16_t);
+
+template <typename T>
+static void writeState(uint8_t*& buffer, const T& value) {
+    memcpy(buffer, &value, sizeof(T));
+    buffer += sizeof(T);
+}
+
+template <typename T>
+static void readState(const uint8_t*& buffer, T& value) {
+    memcpy(&value, buffer, sizeof(T));
+    buffer += sizeof(T);
+}
 
 void noOpSleep(avr_t* avr, avr_cycle_count_t how_long) {}
 
-Arduous::Arduous() = default;
+struct Arduous::IoWriteHookT {
+    Arduous* self;
+    uint16_t address;
+    IoWriteHook hook;
+    // The write handler this hook wraps, called to perform the write; null for plain registers
+    avr_io_write_t original;
+    void* originalParam;
+};
+
+// simavr's logger is global, so map each simulated cpu back to its owner
+static std::map<avr_t*, Arduous*>& instances() {
+    static std::map<avr_t*, Arduous*> instances;
+    return instances;
+}
+
+Arduous::Arduous() {
+    logLevels.fill(ArduousLogLevel::Warning);
+}
+
+Arduous::~Arduous() {
+    unlinkSerial();
+    destroyCpu();
+}
+
+void Arduous::destroyCpu() {
+    if (!cpu) {
+        return;
+    }
+    instances().erase(cpu);
+    // avr_terminate frees flash, data and the peripherals' buffers, but not the avr_t itself
+    avr_terminate(cpu);
+    free(cpu);
+    cpu = nullptr;
+}
+
+// void Arduous::loadFirmware(std::string path) {
+//     elf_firmware_t firmware;
+//     elf_read_firmware(path.c_str(), &firmware);
+//     fprintf(stderr, "Using mcu %s at frequency %d", firmware.mmcu, firmware.frequency);
+//     if (strlen(firmware.mmcu) > 0) {
+//         mmcu = firmware.mmcu;
+//     }
+// }
+
+ArduousLoadResult Arduous::loadHexBuffer(const char* buf, size_t sz) {
+    uint32_t bootSize;
+    uint32_t bootBase;
+    uint8_t* boot = read_ihex_buffer(buf, sz, &bootSize, &bootBase, logFunction(ArduousLogSubsystem::Cpu));
+    if (!boot) {
+        log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Error, "Unable to load buffer");
+        return ArduousLoadResult::InvalidHex;
+    }
+
+    ArduousLoadResult result = loadProgram(boot, bootBase, bootSize);
+    free(boot);
+    return result;
+}
+
+ArduousLoadResult Arduous::loadProgramBytes(const uint8_t* data, size_t size) {
+    std::vector<uint8_t> program(data, data + size);
+    return loadProgram(program.data(), 0, size);
+}
+
+ArduousLoadResult Arduous::loadProgramWords(const uint16_t* words, size_t count) {
+    // AVR flash words are little-endian
+    std::vector<uint8_t> program;
+    program.reserve(count * 2);
+    for (size_t i = 0; i < count; i++) {
+        program.push_back(words[i] & 0xFF);
+        program.push_back(words[i] >> 8);
+    }
+    return loadProgram(program.data(), 0, program.size());
+}
+
+ArduousLoadResult Arduous::swapProgram(const uint8_t* data, size_t size) {
+    return keepingEEPROM([&]() { return loadProgramBytes(data, size); });
+}
+
+ArduousLoadResult Arduous::swapProgramHex(const char* buf, size_t size) {
+    return keepingEEPROM([&]() { return loadHexBuffer(buf, size); });
+}
+
+ArduousLoadResult Arduous::keepingEEPROM(std::function<ArduousLoadResult()> load) {
+    if (!cpu) {
+        return load();
+    }
+    bool dirty = eepromDirty;
+    std::vector<uint8_t> eeprom(getEEPROMSize());
+    saveEEPROM(eeprom.data());
+    ArduousLoadResult result = load();
+    loadEEPROM(eeprom.data(), eeprom.size());
+    eepromDirty = dirty;
+    return result;
+}
+
+ArduousLoadResult Arduous::loadProgram(uint8_t* program, uint32_t base, uint32_t size) {
+    // Flash and boot section sizes come from the mcu definition, so look them up before committing to it
+    avr_t* mcu = avr_make_mcu_by_name(mmcu.c_str());
+    uint32_t flashSize = mcu->flashend + 1;
+    free(mcu);
+    uint32_t bootSectionStart = flashSize - getBootSectionSize();
+
+    if (base + static_cast<uint64_t>(size) > flashSize) {
+        return ArduousLoadResult::TooLarge;
+    }
+    if (base + size > bootSectionStart) {
+        if (!bootloader.empty()) {
+            return ArduousLoadResult::OverlapsBootloader;
+        }
+        warn(ArduousLogSubsystem::Cpu, "Program extends into the bootloader section");
+    }
+
+    this->program.assign(program, program + size);
+    programBase = base;
+    init(program, base, size);
+    return ArduousLoadResult::Ok;
+}
+
+void Arduous::attachFxFlash() {
+    if (!fxFlash) {
+        fxFlash = std::unique_ptr<FxFlash>(new FxFlash());
+        fxFlash->setLog(logFunction(ArduousLogSubsystem::Fx));
+        if (cpu && pins.fxChipSelect.port) {
+            fxFlash->connect(cpu, pins.fxChipSelect.port, pins.fxChipSelect.pin);
+        }
+    }
+}
+
+bool Arduous::loadFxImage(const uint8_t* data, size_t size, uint32_t address) {
+    attachFxFlash();
+    return fxFlash->load(data, size, address);
+}
+
+bool Arduous::loadFxData(const uint8_t* data, size_t size, size_t saveSize) {
+    saveSize = (saveSize + FX_FLASH_SECTOR_SIZE - 1) & ~(FX_FLASH_SECTOR_SIZE - 1);
+    if (size + saveSize > FX_FLASH_SIZE) {
+        return false;
+    }
+    uint32_t saveAddress = FX_FLASH_SIZE - saveSize;
+    uint32_t address = (saveAddress - size) & ~static_cast<uint32_t>(FX_FLASH_PAGE_SIZE - 1);
+    if (!loadFxImage(data, size, address)) {
+        return false;
+    }
+    fxFlash->setSaveRegion(saveAddress, saveSize);
+    return true;
+}
+
+FxFlash* Arduous::getFxFlash() {
+    return fxFlash.get();
+}
+
+ArduousLoadResult Arduous::loadFlashcart(const uint8_t* data, size_t size) {
+    if (!isFlashcartImage(data, size)) {
+        return ArduousLoadResult::InvalidFlashcart;
+    }
+    if (!loadFxImage(data, size, 0)) {
+        return ArduousLoadResult::TooLarge;
+    }
+    flashcartSlots = parseFlashcart(data, size);
+
+    if (!bootloader.empty()) {
+        // Start from an erased application section so the reset lands in the bootloader's menu
+        program.clear();
+        init(nullptr, 0, 0);
+        return ArduousLoadResult::Ok;
+    }
+    for (size_t i = 0; i < flashcartSlots.size(); i++) {
+        if (flashcartSlots[i].programSize > 0) {
+            return bootFlashcartSlot(i);
+        }
+    }
+    return ArduousLoadResult::InvalidFlashcart;
+}
+
+const std::vector<ArduousFlashcartSlot>& Arduous::getFlashcartSlots() {
+    return flashcartSlots;
+}
+
+ArduousLoadResult Arduous::bootFlashcartSlot(size_t index) {
+    if (!fxFlash || index >= flashcartSlots.size()) {
+        return ArduousLoadResult::InvalidFlashcart;
+    }
+    const ArduousFlashcartSlot& slot = flashcartSlots[index];
+    size_t offset = static_cast<size_t>(slot.programPage) * FX_FLASH_PAGE_SIZE;
+    if (slot.programSize == 0 || offset + slot.programSize > FX_FLASH_SIZE) {
+        return ArduousLoadResult::InvalidFlashcart;
+    }
+    ArduousLoadResult result = loadProgramBytes(fxFlash->getData() + offset, slot.programSize);
+    if (slot.savePage != FLASHCART_NO_PAGE && slot.savePage > slot.page) {
+        // The save region runs from its page to the end of the slot
+        uint32_t saveAddress = slot.savePage * FX_FLASH_PAGE_SIZE;
+        uint32_t slotEnd = (slot.page + slot.slotPages) * FX_FLASH_PAGE_SIZE;
+        fxFlash->setSaveRegion(saveAddress, slotEnd > saveAddress ? slotEnd - saveAddress : 0);
+    } else {
+        fxFlash->setSaveRegion(0, 0);
+    }
+    return result;
+}
+
+size_t Arduous::getFxSaveSize() {
+    return fxFlash ? fxFlash->getSaveSize() : 0;
+}
+
+bool Arduous::isFxSaveDirty() {
+    return fxFlash && fxFlash->isSaveDirty();
+}
+
+void Arduous::getFxSave(uint8_t* data) {
+    if (fxFlash) {
+        memcpy(data, fxFlash->getData() + fxFlash->getSaveAddress(), fxFlash->getSaveSize());
+        fxFlash->clearDirtySectors();
+    }
+}
+
+bool Arduous::loadFxSave(const uint8_t* data, size_t size) {
+    if (!fxFlash || size != fxFlash->getSaveSize()) {
+        return false;
+    }
+    memcpy(fxFlash->getData() + fxFlash->getSaveAddress(), data, size);
+    fxFlash->clearDirtySectors();
+    return true;
+}
+
+void Arduous::setFxSaveDirtyCallback(std::function<void()> callback) {
+    attachFxFlash();
+    fxFlash->setSaveDirtyCallback(std::move(callback));
+}
+
+uint32_t Arduous::getProgramCrc32() {
+    return getCrc32(program.data(), program.size());
+}
+
+std::string Arduous::getProgramSha256() {
+    return getSha256(program.data(), program.size());
+}
+
+bool Arduous::identifyProgram(RomDatabase& database, ArduousRomInfo& info) {
+    return database.lookup(getProgramCrc32(), getProgramSha256(), info);
+}
+
+bool Arduous::loadBootloaderHexBuffer(const char* buf, size_t sz) {
+    uint32_t size;
+    uint32_t base;
+    uint8_t* image = read_ihex_buffer(buf, sz, &size, &base, logFunction(ArduousLogSubsystem::Cpu));
+    if (!image) {
+        return false;
+    }
+
+    bootloader.assign(image, image + size);
+    bootloaderBase = base;
+    free(image);
+    return true;
+}
+
+void Arduous::setHardwareVariant(ArduousHardwareVariant variant) {
+    pins = getPinMapping(variant);
+}
+
+void Arduous::setDisplayUpdateBytes(unsigned int bytes) {
+    displayUpdateBytes = std::max(bytes, 1U);
+}
+
+const ArduousDisplayRegisters& Arduous::getDisplayRegisters() {
+    return displayCommands.getRegisters();
+}
+
+ArduousDisplayState Arduous::getDisplayState() {
+    ArduousDisplayAddressing addressing = ArduousDisplayAddressing::Page;
+    if (screen.addr_mode == SSD1306_ADDR_MODE_HORZ) {
+        addressing = ArduousDisplayAddressing::Horizontal;
+    } else if (screen.addr_mode == SSD1306_ADDR_MODE_VERT) {
+        addressing = ArduousDisplayAddressing::Vertical;
+    }
+    return ArduousDisplayState{
+        .addressing = addressing,
+        .cursorPage = screen.cursor.page,
+        .cursorColumn = screen.cursor.column,
+        .contrast = screen.contrast_register,
+        .displayOn = static_cast<bool>(ssd1306_get_flag(&screen, SSD1306_FLAG_DISPLAY_ON)),
+        .inverted = static_cast<bool>(ssd1306_get_flag(&screen, SSD1306_FLAG_DISPLAY_INVERTED)),
+        .segmentRemap = !ssd1306_get_flag(&screen, SSD1306_FLAG_SEGMENT_REMAP_0),
+        .comScanReversed = !ssd1306_get_flag(&screen, SSD1306_FLAG_COM_SCAN_NORMAL),
+        .registers = displayCommands.getRegisters(),
+    };
+}
+
+uint64_t Arduous::getDisplayUpdateCount() {
+    return displayUpdateCount;
+}
+
+unsigned int Arduous::getDisplayFps() {
+    return displayFps;
+}
+
+float Arduous::getDisplayRefreshRate() {
+    return ::getDisplayRefreshRate(displayCommands.getRegisters());
+}
+
+void Arduous::setClockFrequency(uint64_t hz) {
+    freq = hz;
+    cyclesPerVideoFrame = freq / TIMING_FPS;
+    frameCycleRemainder = 0;
+    if (cpu) {
+        cpu->frequency = freq;
+    }
+}
+
+uint64_t Arduous::getClockFrequency() {
+    return freq;
+}
+
+void Arduous::setUsbPowered(bool powered) {
+    usbPowered = powered;
+    if (cpu) {
+        // simavr's USB controller updates USBSTA and raises the VBUS transition interrupt
+        avr_ioctl(cpu, AVR_IOCTL_USB_VBUS, reinterpret_cast<void*>(static_cast<uintptr_t>(powered)));
+    }
+}
+
+bool Arduous::isUsbPowered() {
+    return usbPowered;
+}
+
+void Arduous::setDisplayController(ArduousDisplayController controller) {
+    displayController = controller;
+}
+
+void Arduous::setDisplayInterface(ArduousDisplayInterface bus) {
+    displayInterface = bus;
+}
+
+void Arduous::setFastBoot(bool enabled) {
+    fastBoot = enabled;
+}
+
+void Arduous::setFuses(ArduousFuses newFuses) {
+    fuses = newFuses;
+}
+
+uint32_t Arduous::getBootSectionSize() {
+    // BOOTSZ selects a 256, 512, 1024 or 2048 word boot section at the top of flash
+    unsigned int bootWords = 2048 >> ((fuses.high & FUSE_HIGH_BOOTSZ) >> 1);
+    return bootWords * 2;
+}
+
+uint32_t Arduous::getBootSectionStart() {
+    return cpu->flashend + 1 - getBootSectionSize();
+}
+
+void Arduous::init(uint8_t* boot, uint32_t bootBase, uint32_t bootSize) {
+    destroyCpu();
+    cpu = avr_make_mcu_by_name(mmcu.c_str());
+    instances()[cpu] = this;
+    avr_global_logger_set(Arduous::simavrLogger);
+
+    log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Info, "%s booloader 0x%05x: %d bytes", mmcu.c_str(),
+        (unsigned)bootBase, (int)bootSize);
+
+    avr_init(cpu);
+
+    if (bootSize > 0) {
+        memcpy(cpu->flash + bootBase, boot, bootSize);
+    }
+    cpu->data[MCUSR] = 1 << MCUSR_PORF;
+    cpu->frequency = freq;
+    cpu->sleep = noOpSleep;
+    cpu->pc = bootBase;
+    cpu->codeend = cpu->flashend;
+    fastBootPending = false;
+    cycleTimerSources.clear();
+    // Arrival cycles belong to the old cpu's count
+    linkedSerialInput.clear();
+
+    cpu->fuse[0] = fuses.low;
+    cpu->fuse[1] = fuses.high;
+    cpu->fuse[2] = fuses.extended;
+    // Fuses only change on init, so the boot section isn't decoded again for every instruction
+    bootSectionStart = getBootSectionStart();
+    if (!bootloader.empty()) {
+        if (bootloaderBase + bootloader.size() > cpu->flashend + 1) {
+            warn(ArduousLogSubsystem::Cpu, "Bootloader image doesn't fit in flash, ignoring it");
+        } else {
+            memcpy(cpu->flash + bootloaderBase, bootloader.data(), bootloader.size());
+            // Without a bootloader image the boot section is erased, so only honor BOOTRST with one
+            if (!(fuses.high & FUSE_HIGH_BOOTRST)) {
+                cpu->reset_pc = bootSectionStart;
+                cpu->pc = cpu->reset_pc;
+                fastBootPending = fastBoot;
+                // Caterina gives up waiting for an upload after 8 seconds at most
+                fastBootLimit = cpu->cycle + freq * FAST_BOOT_TIMEOUT_SECONDS;
+            }
+        }
+    }
+    updateSlowPath();
+
+    ssd1306_init(cpu, &screen, DISPLAY_WIDTH, DISPLAY_HEIGHT);
+    ssd1306_wiring_t wiring = {
+        .chip_select = {.port = pins.displayChipSelect.port, .pin = pins.displayChipSelect.pin},
+        .data_instruction = {.port = pins.displayDataCommand.port, .pin = pins.displayDataCommand.pin},
+        .reset = {.port = pins.displayReset.port, .pin = pins.displayReset.pin},
+    };
+    displayCommands.reset();
+    if (displayInterface == ArduousDisplayInterface::I2c) {
+        // simavr's model decodes the same TWI traffic; only the reset line of the wiring is used
+        ssd1306_connect_twi(&screen, &wiring);
+        displayTwiSelected = false;
+        avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_TWI_GETIRQ(0), TWI_IRQ_OUTPUT),
+                                Arduous::displayTwiCallback, this);
+    } else {
+        ssd1306_connect(&screen, &wiring);
+        avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_SPI_GETIRQ(0), SPI_IRQ_OUTPUT),
+                                Arduous::displaySpiCallback, this);
+    }
+
+    pinCallbackParamTs = {PinCallbackParamT{.self = this, .speakerPin = 0},
+                          PinCallbackParamT{.self = this, .speakerPin = 1}};
+
+    // A missing second speaker pin stays low, so a single-pin speaker still sounds
+    speakerPins.reset();
+    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ(pins.speaker1.port), pins.speaker1.pin),
+                            Arduous::soundPinCallback, &pinCallbackParamTs[0]);
+    if (pins.speaker2.port) {
+        avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ(pins.speaker2.port), pins.speaker2.pin),
+                                Arduous::soundPinCallback, &pinCallbackParamTs[1]);
+    }
+
+    for (int i = 0; i < GPIO_PORT_COUNT * 8; i++) {
+        char port = GPIO_FIRST_PORT + i / 8;
+        int pin = i % 8;
+        gpioCallbackParamTs[i] = GpioCallbackParamT{.self = this, .port = port, .pin = pin};
+        avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ(port), pin), Arduous::gpioPinCallback,
+                                &gpioCallbackParamTs[i]);
+    }
+
+    for (auto& hook : ioWriteHooks) {
+        registerIoWriteHook(hook.get());
+    }
+
+    if (fxFlash && pins.fxChipSelect.port) {
+        fxFlash->connect(cpu, pins.fxChipSelect.port, pins.fxChipSelect.pin);
+    }
+
+    // Keep USART1 quiet: no console echo, and no host sleeping while the sketch polls for input
+    uint32_t uartFlags = 0;
+    avr_ioctl(cpu, AVR_IOCTL_UART_SET_FLAGS('1'), &uartFlags);
+    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_UART_GETIRQ('1'), UART_IRQ_OUTPUT),
+                            Arduous::serialOutputCallback, this);
+    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_UART_GETIRQ('1'), UART_IRQ_OUT_XON),
+                            Arduous::serialXonCallback, this);
+    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_UART_GETIRQ('1'), UART_IRQ_OUT_XOFF),
+                            Arduous::serialXoffCallback, this);
+
+    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_ADC_GETIRQ, ADC_IRQ_OUT_TRIGGER), Arduous::adcTriggerCallback,
+                            this);
+    avr_irq_register_notify(avr_iomem_getirq(cpu, EECR, nullptr, AVR_IOMEM_IRQ_ALL), Arduous::eepromControlCallback,
+                            this);
+    eepromMasterEnabled = false;
+
+    cyclesPerVideoFrame = freq / TIMING_FPS;
+    audioBuffer.reserve((audioSampleRate / TIMING_FPS + 1) * 2);
+
+    if (usbPowered) {
+        setUsbPowered(true);
+    }
+
+    if (!pendingEeprom.empty()) {
+        if (!loadEEPROM(pendingEeprom.data(), pendingEeprom.size())) {
+            warn(ArduousLogSubsystem::Eeprom, "EEPROM image doesn't match the EEPROM size, ignoring it");
+        }
+        pendingEeprom.clear();
+    }
+}
+
+void Arduous::reset() {
+    resetWithCause(ArduousResetCause::External);
+}
+
+void Arduous::resetWithCause(ArduousResetCause cause) {
+    // Reset flags accumulate until software clears them, except that a power-on reset clears the rest
+    uint8_t mcusr = cpu->data[MCUSR];
+    // simavr clears all data memory on reset, but only a power-on reset loses SRAM on the real part. The Caterina
+    // bootloader relies on this to find its magic key after a watchdog reset.
+    std::vector<uint8_t> sram(cpu->data + SRAM_START, cpu->data + cpu->ramend + 1);
+    // avr_reset restarts the cycle count; keep it running so queued input, the FX flash's busy time and the frame's
+    // LED and audio timing stay in step
+    avr_cycle_count_t cycle = cpu->cycle;
+    avr_reset(cpu);
+    cpu->cycle = cycle;
+    // Only the bootloader run straight after power-on is fast-forwarded
+    fastBootPending = false;
+    if (cause == ArduousResetCause::PowerOn) {
+        memset(cpu->data + SRAM_START, powerOnRamPattern, sram.size());
+    } else {
+        memcpy(cpu->data + SRAM_START, sram.data(), sram.size());
+    }
+
+    switch (cause) {
+        case ArduousResetCause::PowerOn:
+            mcusr = 1 << MCUSR_PORF;
+            break;
+        case ArduousResetCause::External:
+            mcusr |= 1 << MCUSR_EXTRF;
+            break;
+        case ArduousResetCause::BrownOut:
+            mcusr |= 1 << MCUSR_BORF;
+            break;
+        case ArduousResetCause::Watchdog:
+            mcusr |= 1 << MCUSR_WDRF;
+            break;
+    }
+    cpu->data[MCUSR] = mcusr;
+}
+
+void Arduous::powerCycle() {
+    // Flash survives power loss, including anything the program wrote to it itself
+    std::vector<uint8_t> flash(cpu->flash, cpu->flash + cpu->flashend + 1);
+    avr_cycle_count_t cycle = cpu->cycle;
+    keepingEEPROM([&]() {
+        init(program.data(), programBase, program.size());
+        return ArduousLoadResult::Ok;
+    });
+    memcpy(cpu->flash, flash.data(), flash.size());
+    // The new cpu's cycle count starts over, and init measured the fast boot limit from there
+    cpu->cycle = cycle;
+    fastBootLimit += cycle;
+    memset(cpu->data + SRAM_START, powerOnRamPattern, cpu->ramend + 1 - SRAM_START);
+}
+
+void Arduous::setPowerOnRamPattern(uint8_t pattern) {
+    powerOnRamPattern = pattern;
+}
+
+ArduousFrameStats Arduous::emulateFrame() {
+    if (fastBootPending) {
+        fastForwardBootloader();
+    }
+    // Start where the last frame should have ended, unless the cpu was replaced or reset in between
+    bool continuing = frameEndCycle <= cpu->cycle && cpu->cycle - frameEndCycle < cyclesPerVideoFrame;
+    frameStartCycle = continuing ? frameEndCycle : cpu->cycle;
+    frameCycleRemainder += freq % TIMING_FPS;
+    frameEndCycle = frameStartCycle + cyclesPerVideoFrame + frameCycleRemainder / TIMING_FPS;
+    frameCycleRemainder %= TIMING_FPS;
+    audioSampleRemainder += audioSampleRate;
+    audioSamplesThisFrame = audioSampleRemainder / TIMING_FPS;
+    audioSampleRemainder %= TIMING_FPS;
+    audioBuffer.clear();
+    audioAccumulator = {0, 0};
+    audioAccumulatedUntil = frameStartCycle;
+    ledLitCycles = {0, 0, 0};
+    ledIntegratedUntil = frameStartCycle;
+
+    ArduousFrameStats stats = {0, 0};
+    uint64_t firstCycle = cpu->cycle;
+    while (cpu->cycle < frameEndCycle) {
+        stats.instructions++;
+        if (!step()) {
+            break;
+        }
+    }
+    stats.cycles = cpu->cycle - firstCycle;
+
+    frameCount++;
+    checkFrameWarnings();
+
+    integrateLeds();
+    uint64_t frameCycles = std::max<uint64_t>(ledIntegratedUntil - frameStartCycle, 1);
+    rgbLed = {static_cast<uint8_t>(std::min<uint64_t>(ledLitCycles[0] * 255 / frameCycles, 255)),
+              static_cast<uint8_t>(std::min<uint64_t>(ledLitCycles[1] * 255 / frameCycles, 255)),
+              static_cast<uint8_t>(std::min<uint64_t>(ledLitCycles[2] * 255 / frameCycles, 255))};
+
+    extendAudioBuffer();
+    if (toneActive && cpu->cycle - lastSpeakerChangeCycle > freq / TONE_SILENCE_DIVISOR) {
+        toneActive = false;
+        emitEvent(ArduousEventType::ToneStopped, 0);
+    }
+    emitEvent(ArduousEventType::AudioBlock, audioBuffer.size() / 2);
+    emitEvent(ArduousEventType::DisplayFrameReady, frameCount);
+
+    updateDirtyColumns();
+    // Peers emulating the same frames learn the same sources in the same order, so their states stay interchangeable
+    learnCycleTimerSources();
+    if (!watchList.empty()) {
+        watchList.sample(frameCount, cpu->data, cpu->ramend + 1);
+    }
+    if (pixelChangeTracking) {
+        updateChangedPixelRuns();
+    }
+    if (persistenceAttack > 0 || persistenceDecay > 0) {
+        updatePersistence(frameBlending > 1 ? getBlendedFrameBuffer() : getIntensityFrameBuffer(),
+                          static_cast<float>(stats.cycles) / freq);
+    }
+    return stats;
+}
+
+void Arduous::fastForwardBootloader() {
+    uint64_t budget = std::min<uint64_t>(cpu->cycle + cyclesPerVideoFrame * FAST_BOOT_FRAMES_PER_FRAME, fastBootLimit);
+    bool running = true;
+    while (running && cpu->pc >= bootSectionStart && cpu->cycle < budget) {
+        running = step();
+    }
+    if (!running || cpu->pc < bootSectionStart) {
+        fastBootPending = false;
+    } else if (cpu->cycle >= fastBootLimit) {
+        fastBootPending = false;
+        warn(ArduousLogSubsystem::Cpu, "Bootloader didn't start the application, continuing at normal speed");
+    }
+}
+
+void Arduous::emulateFrame(ArduousFrame& frame) {
+    frame.stats = emulateFrame();
+    frame.display = getVideoFrameBuffer();
+    frame.intensity = frameBlending > 1 ? getBlendedFrameBuffer() : getIntensityFrameBuffer();
+    // Contrast, inversion and blending change the image without touching display RAM, so compare the output
+    frame.displayChanged = frame.intensity != previousIntensity;
+    previousIntensity = frame.intensity;
+    frame.audio = audioBuffer;
+    frame.rgbLed = rgbLed;
+    frame.leds = litLeds;
+}
+
+bool Arduous::step() {
+    if (slowPath) {
+        if (!inputQueue.empty() && inputQueue.begin()->first <= cpu->cycle) {
+            applyQueuedInput();
+        }
+        if (!linkedSerialInput.empty() && linkedSerialInput.front().first <= cpu->cycle) {
+            applyLinkedSerialInput();
+        }
+        if (blendSamplePending) {
+            blendSamplePending = false;
+            blendFrames.push_back(getIntensityFrameBuffer());
+            while (blendFrames.size() > frameBlending) {
+                blendFrames.pop_front();
+            }
+            updateSlowPath();
+        }
+        if (debugChecks) {
+            recordPcHistory();
+        }
+    }
+    int state = avr_run(cpu);
+    bool running = true;
+    switch (state) {
+        case cpu_Done:
+            // TODO(jmaroeder): exit cleanly
+            if (lastCpuState != state) {
+                warn(ArduousLogSubsystem::Cpu, "CPU has stopped");
+            }
+            running = false;
+            break;
+        case cpu_Crashed:
+            // TODO(jmaroeder): exit cleanly
+            if (lastCpuState != state) {
+                recordAudit(&crashReport, "CPU crashed");
+                buildCrashReport();
+                log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Error, "%s", crashReport.c_str());
+                warn(ArduousLogSubsystem::Cpu, "CPU has crashed");
+            }
+            running = false;
+            break;
+        default:
+            break;
+    }
+    lastCpuState = state;
+    if (slowPath) {
+        if (debugChecks) {
+            checkStackPointer();
+        }
+        if (!bootloader.empty()) {
+            bool nowInBootloader = cpu->pc >= bootSectionStart;
+            if (nowInBootloader && !inBootloader) {
+                emitEvent(ArduousEventType::BootloaderEntered, cpu->pc);
+            }
+            inBootloader = nowInBootloader;
+        }
+    }
+    return running;
+}
+
+void Arduous::updateSlowPath() {
+    slowPath = debugChecks || !bootloader.empty() || !inputQueue.empty() || !linkedSerialInput.empty() ||
+               blendSamplePending;
+}
+
+void Arduous::setDebugChecks(bool enabled) {
+    debugChecks = enabled;
+    stackFault = false;
+    updateSlowPath();
+}
+
+void Arduous::setPcHistorySize(size_t entries) {
+    pcHistory.assign(entries, ArduousPcHistoryEntry{});
+    pcHistoryNext = 0;
+    pcHistoryCount = 0;
+}
+
+std::vector<ArduousPcHistoryEntry> Arduous::getPcHistory() {
+    std::vector<ArduousPcHistoryEntry> history;
+    for (size_t i = pcHistoryCount; i > 0; i--) {
+        history.push_back(pcHistory[(pcHistoryNext + pcHistory.size() - i) % pcHistory.size()]);
+    }
+    return history;
+}
+
+std::string Arduous::getCrashReport() {
+    return crashReport;
+}
+
+void Arduous::setAuditMode(bool enabled) {
+    auditMode = enabled;
+    auditEntries.clear();
+}
+
+std::vector<ArduousAuditEntry> Arduous::getAuditReport() {
+    std::vector<ArduousAuditEntry> report;
+    for (const auto& entry : auditEntries) {
+        report.push_back(entry.second);
+    }
+    std::sort(report.begin(), report.end(),
+              [](const ArduousAuditEntry& a, const ArduousAuditEntry& b) { return a.count > b.count; });
+    return report;
+}
+
+bool Arduous::recordAudit(const void* kind, const std::string& message) {
+    if (!auditMode) {
+        return true;
+    }
+    auto key = std::make_pair(kind, cpu->pc);
+    auto entry = auditEntries.find(key);
+    if (entry != auditEntries.end()) {
+        entry->second.count++;
+        return false;
+    }
+    auditEntries[key] = ArduousAuditEntry{message, cpu->pc, 1};
+    return true;
+}
+
+void Arduous::recordPcHistory() {
+    if (pcHistory.empty()) {
+        return;
+    }
+    pcHistory[pcHistoryNext] = ArduousPcHistoryEntry{cpu->pc, getStackPointer(), getStatusRegister()};
+    pcHistoryNext = (pcHistoryNext + 1) % pcHistory.size();
+    pcHistoryCount = std::min(pcHistoryCount + 1, pcHistory.size());
+}
+
+uint8_t Arduous::getStatusRegister() {
+    uint8_t sreg = 0;
+    for (int i = 0; i < 8; i++) {
+        sreg |= (cpu->sreg[i] ? 1 : 0) << i;
+    }
+    return sreg;
+}
+
+void Arduous::buildCrashReport() {
+    std::ostringstream report;
+    report << std::hex << std::setfill('0');
+    report << "CPU crashed at PC=0x" << std::setw(4) << cpu->pc << " SP=0x" << std::setw(4) << getStackPointer()
+           << " SREG=0x" << std::setw(2) << static_cast<int>(getStatusRegister()) << " cycle=" << std::dec
+           << cpu->cycle << std::hex << "\n";
+
+    for (int r = 0; r < 32; r++) {
+        report << "r" << std::dec << std::setfill(' ') << std::setw(2) << r << std::hex << std::setfill('0') << "=0x"
+               << std::setw(2) << static_cast<int>(cpu->data[r]) << ((r % 8 == 7) ? "\n" : " ");
+    }
+
+    // No disassembler is available, so show the raw opcode words around the fault
+    report << "flash around PC:\n";
+    uint32_t start = cpu->pc >= 8 ? cpu->pc - 8 : 0;
+    for (uint32_t address = start & ~1U; address <= cpu->pc + 8 && address + 1 <= cpu->flashend; address += 2) {
+        uint16_t opcode = cpu->flash[address] | (cpu->flash[address + 1] << 8);
+        report << (address == cpu->pc ? " > " : "   ") << std::setw(4) << address << ": " << std::setw(4) << opcode
+               << "\n";
+    }
+
+    report << "recent PCs (oldest first):\n";
+    for (const auto& entry : getPcHistory()) {
+        report << "   " << std::setw(4) << entry.pc << " SP=" << std::setw(4) << entry.sp << " SREG=" << std::setw(2)
+               << static_cast<int>(entry.sreg) << "\n";
+    }
+    crashReport = report.str();
+}
+
+void Arduous::checkStackPointer() {
+    // SP points at the next free byte, so one below the start of SRAM is still a (full) valid stack
+    uint16_t sp = getStackPointer();
+    bool inSram = sp >= SRAM_START - 1 && sp <= cpu->ramend;
+    if (inSram) {
+        stackFault = false;
+        return;
+    }
+    if (!stackFault) {
+        stackFault = true;
+        char message[64];
+        snprintf(message, sizeof(message), "Stack %s: SP=0x%04x PC=0x%04x", sp > cpu->ramend ? "underflow" : "overflow",
+                 sp, cpu->pc);
+        warn(ArduousLogSubsystem::Cpu, message);
+        emitEvent(ArduousEventType::StackFault, sp);
+    }
+}
+
+void Arduous::update(int steps) {
+    for (int i = 0; i < steps; i++) {
+        emulateFrame();
+    }
+}
+
+uint64_t Arduous::runForCycles(uint64_t cycles) {
+    return runUntilCycle(cpu->cycle + cycles);
+}
+
+uint64_t Arduous::runUntilCycle(uint64_t cycle) {
+    uint64_t startCycle = cpu->cycle;
+    while (cpu->cycle < cycle) {
+        if (!step()) {
+            break;
+        }
+    }
+    return cpu->cycle - startCycle;
+}
+
+bool Arduous::runUntilPc(uint32_t address, uint64_t maxCycles, uint64_t& elapsedCycles) {
+    uint64_t startCycle = cpu->cycle;
+    while (cpu->pc != address && cpu->cycle - startCycle < maxCycles) {
+        if (!step()) {
+            break;
+        }
+    }
+    elapsedCycles = cpu->cycle - startCycle;
+    if (cpu->pc != address) {
+        return false;
+    }
+    emitEvent(ArduousEventType::Breakpoint, cpu->pc);
+    return true;
+}
+
+bool Arduous::runUntilReturn(uint64_t maxCycles, uint64_t& elapsedCycles) {
+    // Pushes and pops inside the function (and interrupts) balance out; only popping our own return
+    // address takes the stack pointer above where it started
+    uint16_t startSp = getStackPointer();
+    uint64_t startCycle = cpu->cycle;
+    while (getStackPointer() <= startSp && cpu->cycle - startCycle < maxCycles) {
+        if (!step()) {
+            break;
+        }
+    }
+    elapsedCycles = cpu->cycle - startCycle;
+    return getStackPointer() > startSp;
+}
+
+uint16_t Arduous::getStackPointer() {
+    return cpu->data[SPL] | (cpu->data[SPH] << 8);
+}
+
+void Arduous::setButtonState(ArduousButtonState newButtonState) {
+    buttonState = newButtonState;
+    // Buttons pull their pins low when pressed. Raising the pins (rather than poking PINx) lets
+    // pin-change interrupts see presses, which Arduboy2's sleep and waitNoButtons paths rely on.
+    setInputPin(pins.buttonUp, !buttonState.buttonUp);
+    setInputPin(pins.buttonRight, !buttonState.buttonRight);
+    setInputPin(pins.buttonLeft, !buttonState.buttonLeft);
+    setInputPin(pins.buttonDown, !buttonState.buttonDown);
+    setInputPin(pins.buttonA, !buttonState.buttonA);
+    setInputPin(pins.buttonB, !buttonState.buttonB);
+}
+
+void Arduous::setButtons(uint8_t buttons) {
+    ArduousButtonState state;
+    state.buttonUp = buttons & ARDUOUS_BUTTON_UP;
+    state.buttonRight = buttons & ARDUOUS_BUTTON_RIGHT;
+    state.buttonLeft = buttons & ARDUOUS_BUTTON_LEFT;
+    state.buttonDown = buttons & ARDUOUS_BUTTON_DOWN;
+    state.buttonA = buttons & ARDUOUS_BUTTON_A;
+    state.buttonB = buttons & ARDUOUS_BUTTON_B;
+    setButtonState(state);
+}
+
+uint8_t Arduous::getButtons() {
+    return (buttonState.buttonUp ? ARDUOUS_BUTTON_UP : 0) | (buttonState.buttonRight ? ARDUOUS_BUTTON_RIGHT : 0) |
+           (buttonState.buttonLeft ? ARDUOUS_BUTTON_LEFT : 0) | (buttonState.buttonDown ? ARDUOUS_BUTTON_DOWN : 0) |
+           (buttonState.buttonA ? ARDUOUS_BUTTON_A : 0) | (buttonState.buttonB ? ARDUOUS_BUTTON_B : 0);
+}
+
+void Arduous::queueInput(uint64_t atCycle, ArduousButton button, bool pressed) {
+    // Inputs queued for the same cycle are applied in the order they were queued
+    inputQueue.emplace(atCycle, std::make_pair(button, pressed));
+    updateSlowPath();
+}
+
+void Arduous::clearQueuedInput() {
+    inputQueue.clear();
+    updateSlowPath();
+}
+
+void Arduous::applyQueuedInput() {
+    ArduousButtonState state = buttonState;
+    while (!inputQueue.empty() && inputQueue.begin()->first <= cpu->cycle) {
+        bool pressed = inputQueue.begin()->second.second;
+        switch (inputQueue.begin()->second.first) {
+            case ArduousButton::Up:
+                state.buttonUp = pressed;
+                break;
+            case ArduousButton::Right:
+                state.buttonRight = pressed;
+                break;
+            case ArduousButton::Left:
+                state.buttonLeft = pressed;
+                break;
+            case ArduousButton::Down:
+                state.buttonDown = pressed;
+                break;
+            case ArduousButton::A:
+                state.buttonA = pressed;
+                break;
+            case ArduousButton::B:
+                state.buttonB = pressed;
+                break;
+        }
+        inputQueue.erase(inputQueue.begin());
+    }
+    setButtonState(state);
+    updateSlowPath();
+}
+
+void Arduous::setInputPin(ArduousPin pin, bool value) {
+    if (pin.port) {
+        avr_raise_irq(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ(pin.port), pin.pin), value);
+    }
+}
+
+std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> Arduous::getVideoFrameBuffer() {
+    std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> fb;
+
+    ArduousDisplayScan scan;
+    scan.segmentRemap0 = ssd1306_get_flag(&screen, SSD1306_FLAG_SEGMENT_REMAP_0);
+    scan.comScanNormal = ssd1306_get_flag(&screen, SSD1306_FLAG_COM_SCAN_NORMAL);
+    scan.columnOffset = displayController == ArduousDisplayController::Sh1106 ? SH1106_COLUMN_OFFSET : 0;
+    // Inverse display (0xA7) lights the pixels whose RAM bit is clear, on the driven rows only
+    bool inverted = ssd1306_get_flag(&screen, SSD1306_FLAG_DISPLAY_INVERTED);
+    const ArduousDisplayRegisters& registers = displayCommands.getRegisters();
+    for (unsigned int y = 0; y < DISPLAY_HEIGHT; y++) {
+        for (unsigned int x = 0; x < DISPLAY_WIDTH; x++) {
+            unsigned int column;
+            unsigned int row;
+            if (!getDisplayRamPixel(registers, scan, x, y, column, row)) {
+                break;
+            }
+            if (static_cast<bool>(screen.vram[row / 8][column] & (1 << (row % 8))) != inverted) {
+                fb[y * DISPLAY_WIDTH + x] = true;
+            }
+        }
+    }
+
+    return fb;
+}
+
+std::array<uint8_t, DISPLAY_WIDTH * DISPLAY_HEIGHT / 8> Arduous::getDisplayPages() {
+    std::array<uint8_t, DISPLAY_WIDTH * DISPLAY_HEIGHT / 8> pages;
+    int columnOffset = displayController == ArduousDisplayController::Sh1106 ? SH1106_COLUMN_OFFSET : 0;
+    for (int p = 0; p < DISPLAY_HEIGHT / 8; p++) {
+        for (int c = 0; c < DISPLAY_WIDTH; c++) {
+            pages[p * DISPLAY_WIDTH + c] = screen.vram[p][(c + columnOffset) % DISPLAY_WIDTH];
+        }
+    }
+    return pages;
+}
+
+std::vector<uint8_t> Arduous::getIntensityFrameBuffer() {
+    std::vector<uint8_t> intensity(DISPLAY_WIDTH * DISPLAY_HEIGHT, 0);
+    if (!ssd1306_get_flag(&screen, SSD1306_FLAG_DISPLAY_ON)) {
+        return intensity;
+    }
+    // Segment current scales with contrast, but even contrast 0 leaves lit pixels faintly visible
+    uint8_t level = CONTRAST_MIN_INTENSITY + screen.contrast_register * (255 - CONTRAST_MIN_INTENSITY) / 255;
+    auto fb = getVideoFrameBuffer();
+    for (size_t i = 0; i < fb.size(); i++) {
+        if (fb[i]) {
+            intensity[i] = level;
+        }
+    }
+    return intensity;
+}
+
+void Arduous::setFrameBlending(unsigned int updates) {
+    frameBlending = updates;
+    blendFrames.clear();
+}
+
+std::vector<uint8_t> Arduous::getBlendedFrameBuffer() {
+    if (blendFrames.empty()) {
+        return getIntensityFrameBuffer();
+    }
+    std::vector<uint8_t> blended(DISPLAY_WIDTH * DISPLAY_HEIGHT);
+    for (size_t i = 0; i < blended.size(); i++) {
+        unsigned int sum = 0;
+        for (const auto& frame : blendFrames) {
+            sum += frame[i];
+        }
+        blended[i] = sum / blendFrames.size();
+    }
+    return blended;
+}
+
+void Arduous::setPixelPersistence(float attackSeconds, float decaySeconds) {
+    persistenceAttack = attackSeconds;
+    persistenceDecay = decaySeconds;
+    persistence.clear();
+}
+
+std::vector<float> Arduous::getPersistenceFrameBuffer() {
+    if (persistence.empty()) {
+        std::vector<float> current;
+        for (uint8_t level : getIntensityFrameBuffer()) {
+            current.push_back(level / 255.0f);
+        }
+        return current;
+    }
+    return persistence;
+}
+
+void Arduous::updatePersistence(const std::vector<uint8_t>& intensity, float seconds) {
+    if (persistence.empty()) {
+        persistence.assign(intensity.size(), 0.0f);
+    }
+    // Exponential approach, so the result doesn't depend on how long each frame was
+    float attack = persistenceAttack > 0 ? 1.0f - std::exp(-seconds / persistenceAttack) : 1.0f;
+    float decay = persistenceDecay > 0 ? 1.0f - std::exp(-seconds / persistenceDecay) : 1.0f;
+    for (size_t i = 0; i < persistence.size(); i++) {
+        float target = intensity[i] / 255.0f;
+        persistence[i] += (target - persistence[i]) * (target > persistence[i] ? attack : decay);
+    }
+}
+
+void Arduous::setAudioSampleRate(unsigned int rate) {
+    audioSampleRate = rate;
+    audioSampleRemainder = 0;
+    audioBuffer.reserve((audioSampleRate / TIMING_FPS + 1) * 2);
+}
+
+unsigned int Arduous::getAudioSampleRate() {
+    return audioSampleRate;
+}
+
+void Arduous::setAudioSynthesis(ArduousAudioSynthesis synthesis) {
+    audioSynthesis = synthesis;
+}
+
+void Arduous::setAudioChannels(ArduousAudioChannels channels) {
+    audioChannels = channels;
+}
+
+std::vector<int16_t> Arduous::getAudioBuffer() {
+    if (audioBuffer.size() < audioSamplesThisFrame * 2) {
+        extendAudioBuffer();
+    }
+    return audioBuffer;
+}
+
+void Arduous::setPixelChangeTracking(bool enabled) {
+    pixelChangeTracking = enabled;
+    previousVideoFrameBuffer.reset();
+    changedPixelRuns.clear();
+}
+
+std::vector<ArduousPixelRun> Arduous::getChangedPixelRuns() {
+    return changedPixelRuns;
+}
+
+void Arduous::updateChangedPixelRuns() {
+    auto fb = getVideoFrameBuffer();
+    changedPixelRuns.clear();
+
+    for (unsigned int y = 0; y < DISPLAY_HEIGHT; y++) {
+        unsigned int x = 0;
+        while (x < DISPLAY_WIDTH) {
+            unsigned int i = y * DISPLAY_WIDTH + x;
+            if (fb[i] == previousVideoFrameBuffer[i]) {
+                x++;
+                continue;
+            }
+            ArduousPixelRun run = {x, y, 0, fb[i]};
+            while (x < DISPLAY_WIDTH && fb[y * DISPLAY_WIDTH + x] != previousVideoFrameBuffer[y * DISPLAY_WIDTH + x] &&
+                   fb[y * DISPLAY_WIDTH + x] == run.lit) {
+                run.length++;
+                x++;
+            }
+            changedPixelRuns.push_back(run);
+        }
+    }
+
+    previousVideoFrameBuffer = fb;
+}
+
+WatchList& Arduous::getWatchList() {
+    return watchList;
+}
+
+bool Arduous::loadWatchSymbols(const std::vector<uint8_t>& elf) {
+    std::map<std::string, uint32_t> symbols = readElfDataSymbols(elf.data(), elf.size());
+    if (symbols.empty()) {
+        return false;
+    }
+    watchList.setSymbols(std::move(symbols));
+    return true;
+}
+
+const ArduousDirtyColumns& Arduous::getDirtyColumns() {
+    return dirtyColumns;
+}
+
+bool Arduous::isDisplayDirty() {
+    for (const auto& page : dirtyColumns) {
+        if (page.any()) {
+            return true;
+        }
+    }
+    return false;
+}
+
+void Arduous::updateDirtyColumns() {
+    auto pages = getDisplayPages();
+    for (unsigned int p = 0; p < DISPLAY_HEIGHT / 8; p++) {
+        dirtyColumns[p].reset();
+        for (unsigned int c = 0; c < DISPLAY_WIDTH; c++) {
+            dirtyColumns[p][c] = pages[p * DISPLAY_WIDTH + c] != previousDisplayPages[p * DISPLAY_WIDTH + c];
+        }
+    }
+    previousDisplayPages = pages;
+}
+
+std::vector<uint8_t> Arduous::takeSerialOutput() {
+    std::vector<uint8_t> output;
+    output.swap(serialOutput);
+    return output;
+}
+
+void Arduous::sendSerialInput(const uint8_t* data, size_t size) {
+    serialInput.insert(serialInput.end(), data, data + size);
+    pumpSerialInput();
+}
+
+void Arduous::linkSerial(Arduous& other) {
+    if (&other == this) {
+        return;
+    }
+    unlinkSerial();
+    other.unlinkSerial();
+    serialPeer = &other;
+    other.serialPeer = this;
+}
+
+void Arduous::unlinkSerial() {
+    if (serialPeer) {
+        serialPeer->serialPeer = nullptr;
+        serialPeer = nullptr;
+    }
+}
+
+void Arduous::pumpSerialInput() {
+    // The UART raises XOFF when its receive FIFO is full, so only feed it while it's ready
+    while (cpu && serialInputReady && !serialInput.empty()) {
+        uint8_t byte = serialInput.front();
+        serialInput.pop_front();
+        avr_raise_irq(avr_io_getirq(cpu, AVR_IOCTL_UART_GETIRQ('1'), UART_IRQ_INPUT), byte);
+    }
+}
+
+void Arduous::queueLinkedSerialInput(uint64_t frameCycle, uint8_t byte) {
+    if (!cpu) {
+        return;
+    }
+    // The cpu sits at the start of the frame it emulates next, whether or not the sender's frame came first
+    uint64_t arrival = cpu->cycle + frameCycle;
+    if (!linkedSerialInput.empty()) {
+        arrival = std::max(arrival, linkedSerialInput.back().first);
+    }
+    linkedSerialInput.emplace_back(arrival, byte);
+    updateSlowPath();
+}
+
+void Arduous::applyLinkedSerialInput() {
+    while (!linkedSerialInput.empty() && linkedSerialInput.front().first <= cpu->cycle) {
+        serialInput.push_back(linkedSerialInput.front().second);
+        linkedSerialInput.pop_front();
+    }
+    pumpSerialInput();
+    updateSlowPath();
+}
 
-// void Arduous::loadFirmware(std::string path) {
-//     elf_firmware_t firmware;
-//     elf_read_firmware(path.c_str(), &firmware);
-//     fprintf(stderr, "Using mcu %s at frequency %d", firmware.mmcu, firmware.frequency);
-//     if (strlen(firmware.mmcu) > 0) {
-//         mmcu = firmware.mmcu;
-//     }
-// }
+void Arduous::setAdcInputHook(AdcInputHook hook) {
+    adcInputHook = std::move(hook);
+}
 
-    // decode line text hex to binary
-static int read_hex_string_buf(const char * src, const char *end, uint8_t * buffer, int maxlen)
-{
-    uint8_t * dst = buffer;
-    int ls = 0;
-    uint8_t b = 0;
-    while (src < end && maxlen) {
-        char c = *src++;
-        switch (c) {
-            case 'a' ... 'f':   b = (b << 4) | (c - 'a' + 0xa); break;
-            case 'A' ... 'F':   b = (b << 4) | (c - 'A' + 0xa); break;
-            case '0' ... '9':   b = (b << 4) | (c - '0'); break;
-            default:
-                if (c > ' ') {
-                    fprintf(stderr, "%s: huh '%c' (%s)\n", __FUNCTION__, c, src);
-                    return -1;
-                }
-                continue;
-        }
-        if (ls & 1) {
-            *dst++ = b; b = 0;
-            maxlen--;
-        }
-        ls++;
-    }
-
-    return dst - buffer;
-}
-
-static int
-read_ihex_chunks_buffer(const char *buf, const char *end,
-		ihex_chunk_p * chunks )
-{
-    if (!buf || !chunks)
-	return -1;
-    uint32_t segment = 0;	// segment address
-    int chunk = 0, max_chunks = 0;
-    const char *nextlineptr = buf;
-    *chunks = NULL;
-
-    while (nextlineptr < end) {
-	const char *lineptr, *lineend;
-	lineptr = nextlineptr;
-
-	// Find end of the line
-	for (lineend = lineptr;
-	     lineend < end && !isspace(*lineend);
-	     lineend++);
-
-	// Find beginning of the next line. Skip all the whitespaces 
-	for (nextlineptr = lineend;
-	     nextlineptr < end && isspace(*nextlineptr);
-	     nextlineptr++);
-	    
-	if (lineptr[0] != ':') {
-	    fprintf(stderr, "AVR: invalid ihex format (%.4s)\n", lineptr);
-	    break;
-	}
-	uint8_t bline[64];
-
-	int len = read_hex_string_buf(lineptr + 1, lineend,
-				      bline, sizeof(bline));
-	if (len <= 0)
-	    continue;
-
-	uint8_t chk = 0;
-	{	// calculate checksum
-	    uint8_t * src = bline;
-	    int tlen = len-1;
-	    while (tlen--)
-		chk += *src++;
-	    chk = 0x100 - chk;
-	}
-	if (chk != bline[len-1]) {
-	    fprintf(stderr, "%s: invalid checksum %02x/%02x\n", __FUNCTION__, chk, bline[len-1]);
-	    break;
-	}
-	uint32_t addr = 0;
-	switch (bline[3]) {
-	case 0: // normal data
-	    addr = segment | (bline[1] << 8) | bline[2];
-	    break;
-	case 1: // end of file
-	    continue;
-	case 2: // extended address 2 bytes
-	    segment = ((bline[4] << 8) | bline[5]) << 4;
-	    continue;
-	case 4:
-	    segment = ((bline[4] << 8) | bline[5]) << 16;
-	    continue;
-	default:
-	    fprintf(stderr, "%s: unsupported check type %02x\n", __FUNCTION__, bline[3]);
-	    continue;
-	}
-	if (chunk < max_chunks && addr != ((*chunks)[chunk].baseaddr + (*chunks)[chunk].size)) {
-	    if ((*chunks)[chunk].size)
-		chunk++;
-	}
-	if (chunk >= max_chunks) {
-	    max_chunks++;
-	    /* Here we allocate and zero an extra chunk, to act as terminator */
-	    *chunks = (ihex_chunk_p)realloc(*chunks, (1 + max_chunks) * sizeof(ihex_chunk_t));
-	    memset(*chunks + chunk, 0,
-		   (1 + (max_chunks - chunk)) * sizeof(ihex_chunk_t));
-	    (*chunks)[chunk].baseaddr = addr;
-	}
-	(*chunks)[chunk].data = (uint8_t *) realloc((*chunks)[chunk].data,
-					(*chunks)[chunk].size + bline[0]);
-	memcpy((*chunks)[chunk].data + (*chunks)[chunk].size,
-	       bline + 4, bline[0]);
-	(*chunks)[chunk].size += bline[0];
-    }
-    return max_chunks;
-}
-
-
-static uint8_t *
-read_ihex_buffer(const char *data, size_t sz, uint32_t * dsize, uint32_t * start)
-{
-	ihex_chunk_p chunks = NULL;
-	int count = read_ihex_chunks_buffer(data, data + sz, &chunks);
-	uint8_t * res = NULL;
-
-	if (count > 0) {
-		*dsize = chunks[0].size;
-		*start = chunks[0].baseaddr;
-		res = chunks[0].data;
-		chunks[0].data = NULL;
-	}
-	if (count > 1) {
-		fprintf(stderr, "AVR: ihex contains more chunks than loaded (%d)\n",
-				count);
-	}
-	free_ihex_chunks(chunks);
-	return res;
-}
-
-
-void Arduous::loadHexBuffer(const char *buf, size_t sz) {
-    uint32_t bootSize;
-    uint32_t bootBase;
-    uint8_t* boot = read_ihex_buffer(buf, sz, &bootSize, &bootBase);
-    if (!boot) {
-        fprintf(stderr, "Unable to load buffer\n");
-        exit(1);
+uint32_t Arduous::readAdcInput(int channel) {
+    if (adcInputHook) {
+        return adcInputHook(channel);
+    }
+    if (channel == ADC_CHANNEL_TEMPERATURE) {
+        // Roughly 25 degrees C on the 32u4's sensor
+        return 785;
     }
+    // Unconnected inputs float; games seed random() from them, so return reproducible noise
+    adcNoiseState ^= adcNoiseState << 13;
+    adcNoiseState ^= adcNoiseState >> 17;
+    adcNoiseState ^= adcNoiseState << 5;
+    return adcNoiseState % 2560;
+}
 
-    init(boot, bootBase, bootSize);
-    free(boot);
+void Arduous::setWarningCallback(WarningCallback callback) {
+    warningCallback = std::move(callback);
 }
 
-void Arduous::init(uint8_t* boot, uint32_t bootBase, uint32_t bootSize) {
-    cpu = avr_make_mcu_by_name(mmcu.c_str());
+void Arduous::setPinChangeCallback(PinChangeCallback callback) {
+    pinChangeCallback = std::move(callback);
+}
 
-    fprintf(stderr, "%s booloader 0x%05x: %d bytes\n", mmcu.c_str(), (unsigned) bootBase, (int) bootSize);
+void Arduous::setLogLevel(ArduousLogSubsystem subsystem, ArduousLogLevel level) {
+    logLevels[static_cast<size_t>(subsystem)] = level;
+}
 
-    avr_init(cpu);
+void Arduous::setLogSink(ArduousLogSink sink) {
+    logSink = std::move(sink);
+}
 
-    memcpy(cpu->flash + bootBase, boot, bootSize);
-    cpu->frequency = freq;
-    cpu->sleep = noOpSleep;
-    cpu->pc = bootBase;
-    cpu->codeend = cpu->flashend;
+bool Arduous::isLogging(ArduousLogSubsystem subsystem, ArduousLogLevel level) {
+    return level != ArduousLogLevel::None && level <= logLevels[static_cast<size_t>(subsystem)];
+}
 
-    ssd1306_init(cpu, &screen, DISPLAY_WIDTH, DISPLAY_HEIGHT);
-    ssd1306_wiring_t wiring = {
-        .chip_select = {.port = 'D', .pin = 6},
-        .data_instruction = {.port = 'D', .pin = 4},
-        .reset = {.port = 'D', .pin = 7},
+ArduousLogFunction Arduous::logFunction(ArduousLogSubsystem subsystem) {
+    return [this, subsystem](ArduousLogLevel level, const std::string& message) {
+        log(subsystem, level, "%s", message.c_str());
     };
-    ssd1306_connect(&screen, &wiring);
+}
 
-    pinCallbackParamTs = {PinCallbackParamT{.self = this, .speakerPin = 0},
-                          PinCallbackParamT{.self = this, .speakerPin = 1}};
+void Arduous::log(ArduousLogSubsystem subsystem, ArduousLogLevel level, const char* format, ...) {
+    if (!isLogging(subsystem, level)) {
+        return;
+    }
 
-    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ('C'), 6), Arduous::soundPinCallback,
-                            &pinCallbackParamTs[0]);
-    avr_irq_register_notify(avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ('C'), 7), Arduous::soundPinCallback,
-                            &pinCallbackParamTs[1]);
+    va_list ap;
+    va_start(ap, format);
+    va_list measure;
+    va_copy(measure, ap);
+    std::vector<char> buffer(vsnprintf(nullptr, 0, format, measure) + 1);
+    va_end(measure);
+    vsnprintf(buffer.data(), buffer.size(), format, ap);
+    va_end(ap);
 
-    cyclesPerVideoFrame = freq / TIMING_FPS;
-    cyclesPerAudioSample = freq / TIMING_SAMPLE_RATE;
-    audioSamplesPerVideoFrame = TIMING_SAMPLE_RATE / TIMING_FPS;
-    audioBuffer.reserve(audioSamplesPerVideoFrame * 2);
+    if (logSink) {
+        logSink(subsystem, level, buffer.data());
+    } else {
+        std::cerr << "[" << getLogSubsystemName(subsystem) << "] " << buffer.data() << "\n";
+    }
 }
 
-void Arduous::reset() {
-    cpu->pc = cpu->reset_pc;
+bool Arduous::addIoWriteHook(uint16_t address, IoWriteHook hook) {
+    // simavr indexes its I/O table with the address unchecked, so anything outside I/O space would corrupt memory
+    uint16_t ioEnd = cpu ? cpu->ioend : SRAM_START - 1;
+    if (address < IO_START || address > ioEnd) {
+        return false;
+    }
+    ioWriteHooks.emplace_back(new IoWriteHookT{this, address, std::move(hook), nullptr, nullptr});
+    if (cpu) {
+        registerIoWriteHook(ioWriteHooks.back().get());
+    }
+    return true;
 }
 
-void Arduous::emulateFrame() {
-    frameStartCycle = cpu->cycle;
-    frameEndCycle = frameStartCycle + cyclesPerVideoFrame;
-    audioBuffer.clear();
-
-    while (cpu->cycle < frameEndCycle) {
-        int state = avr_run(cpu);
-        switch (state) {
-            case cpu_Done:
-                // TODO(jmaroeder): exit cleanly
-                std::cerr << "cpu state is done\n";
-                break;
-            case cpu_Crashed:
-                // TODO(jmaroeder): exit cleanly
-                std::cerr << "cpu state is crashed\n";
-                break;
-            default:
-                break;
+void Arduous::clearIoWriteHooks() {
+    if (cpu) {
+        // Hooks on the same register wrap each other, so unwrap them newest first
+        for (auto hook = ioWriteHooks.rbegin(); hook != ioWriteHooks.rend(); ++hook) {
+            auto& io = cpu->io[AVR_DATA_TO_IO((*hook)->address)];
+            io.w.c = (*hook)->original;
+            io.w.param = (*hook)->originalParam;
         }
     }
+    ioWriteHooks.clear();
 }
 
-void Arduous::update(int steps) {
-    for (int i = 0; i < steps; i++) {
-        emulateFrame();
+void Arduous::addEventCallback(EventCallback callback) {
+    eventCallbacks.push_back(std::move(callback));
+}
+
+void Arduous::setEventQueueEnabled(bool enabled) {
+    eventQueueEnabled = enabled;
+    if (!enabled) {
+        eventQueue.clear();
     }
 }
 
-#define PINB 0x23
-#define PINE 0x2c
-#define PINF 0x2f
+std::vector<ArduousEvent> Arduous::takeEvents() {
+    std::vector<ArduousEvent> events;
+    events.swap(eventQueue);
+    return events;
+}
 
-void Arduous::setButtonState(ArduousButtonState newButtonState) {
-    cpu->data[PINB] = (!newButtonState.buttonB << 4) | (cpu->data[PINB] & 0xEF);
-    cpu->data[PINE] = (!newButtonState.buttonA << 6) | (cpu->data[PINE] & 0xBF);
-    cpu->data[PINF] = (!newButtonState.buttonUp << 7) | (!newButtonState.buttonRight << 6) |
-                      (!newButtonState.buttonLeft << 5) | (!newButtonState.buttonDown << 4) | (cpu->data[PINF] & 0x0F);
+void Arduous::emitEvent(ArduousEventType type, uint32_t value) {
+    ArduousEvent event = {type, cpu->cycle, value};
+    for (auto& callback : eventCallbacks) {
+        callback(event);
+    }
+    if (eventQueueEnabled) {
+        eventQueue.push_back(event);
+    }
 }
 
-std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> Arduous::getVideoFrameBuffer() {
-    std::bitset<DISPLAY_WIDTH * DISPLAY_HEIGHT> fb;
+bool Arduous::getPinLevel(ArduousPin pin) {
+    return avr_io_getirq(cpu, AVR_IOCTL_IOPORT_GETIRQ(pin.port), pin.pin)->value & 0x1;
+}
 
-    for (int p = 0; p < screen.pages; p++) {
-        for (int c = 0; c < screen.columns; c++) {
-            uint8_t vram_byte = screen.vram[p][c];
+bool Arduous::isLedLit(ArduousPin pin) {
+    // All the LEDs are wired active-low
+    return pin.port && !getPinLevel(pin);
+}
 
-            for (int i = 0; i < 8; i++) {
-                if (vram_byte & (1 << i)) {
-                    fb[(p * 8 + i) * DISPLAY_WIDTH + c] = true;
-                }
-            }
+void Arduous::integrateLeds() {
+    uint64_t now = std::max(cpu->cycle, ledIntegratedUntil);
+    static const uint32_t channels[] = {ARDUOUS_LED_RED, ARDUOUS_LED_GREEN, ARDUOUS_LED_BLUE};
+    for (unsigned int i = 0; i < 3; i++) {
+        if (litLeds & channels[i]) {
+            ledLitCycles[i] += now - ledIntegratedUntil;
         }
     }
+    ledIntegratedUntil = now;
+}
 
-    return fb;
+ArduousRgbLed Arduous::getRgbLed() {
+    return rgbLed;
 }
 
-std::vector<int16_t> Arduous::getAudioBuffer() {
-    if (audioBuffer.size() < audioSamplesPerVideoFrame * 2) {
-        extendAudioBuffer();
+uint32_t Arduous::getLitLeds() {
+    return litLeds;
+}
+
+void Arduous::updateLeds() {
+    uint32_t leds = (isLedLit(pins.ledRed) ? ARDUOUS_LED_RED : 0) | (isLedLit(pins.ledGreen) ? ARDUOUS_LED_GREEN : 0) |
+                    (isLedLit(pins.ledBlue) ? ARDUOUS_LED_BLUE : 0) | (isLedLit(pins.ledTx) ? ARDUOUS_LED_TX : 0) |
+                    (isLedLit(pins.ledRx) ? ARDUOUS_LED_RX : 0);
+    if (leds != litLeds) {
+        integrateLeds();
+        litLeds = leds;
+        emitEvent(ArduousEventType::LedChanged, leds);
+    }
+}
+
+void Arduous::registerIoWriteHook(IoWriteHookT* hook) {
+    // Wrap the register's write handler rather than watching its IRQ, which only fires once the write has landed
+    auto& io = cpu->io[AVR_DATA_TO_IO(hook->address)];
+    hook->original = io.w.c;
+    hook->originalParam = io.w.param;
+    io.w.c = Arduous::ioWriteCallback;
+    io.w.param = hook;
+}
+
+void Arduous::warn(ArduousLogSubsystem subsystem, const std::string& message) {
+    log(subsystem, ArduousLogLevel::Warning, "%s", message.c_str());
+    if (warningCallback) {
+        warningCallback(message);
+    }
+}
+
+void Arduous::checkFrameWarnings() {
+    if (frameCount == DISPLAY_ON_TIMEOUT_FRAMES && !ssd1306_get_flag(&screen, SSD1306_FLAG_DISPLAY_ON)) {
+        warn(ArduousLogSubsystem::Display, "Display has not been switched on");
+    }
+    if (frameCount % TIMING_FPS == 0) {
+        if (eepromWritesThisSecond > EEPROM_WRITE_STORM_THRESHOLD) {
+            warn(ArduousLogSubsystem::Eeprom,
+                 "EEPROM written " + std::to_string(eepromWritesThisSecond) + " times in one second");
+        }
+        eepromWritesThisSecond = 0;
+        displayFps = displayUpdatesThisSecond;
+        displayUpdatesThisSecond = 0;
     }
-    return audioBuffer;
 }
 
 size_t Arduous::getSaveSize() {
-    size_t size = sizeof(int)                  // cpu->state
+    size_t size = sizeof(uint32_t)                                   // magic
+                  + sizeof(uint32_t)                                 // version
+                  + MAX_CYCLE_TIMERS * SAVE_STATE_CYCLE_TIMER_SIZE   // cpu->cycle_timers
+                  + getPeripheralStateSize()                         // timer, uart and spi internals
+                  + sizeof(uint8_t) + SAVE_STATE_MAX_VECTORS * 2  // cpu->interrupts vectors and pending flags
+                  + sizeof(uint8_t) + SAVE_STATE_MAX_VECTORS      // cpu->interrupts running stack
+                  + sizeof(int)                // cpu->state
                   + sizeof(avr_cycle_count_t)  // cpu->cycle
                   + sizeof(avr_cycle_count_t)  // cpu->run_cycle_count
                   + sizeof(avr_cycle_count_t)  // cpu->run_cycle_limit
@@ -305,13 +1498,32 @@ size_t Arduous::getSaveSize() {
                   + sizeof(ssd1306_addressing_mode_t)                            // screen->addr_mode
                   + sizeof(uint8_t)                                              // screen->twi_selected
                   + sizeof(uint8_t)                                              // screen->twi_index
+                  + sizeof(ArduousDisplayRegisters)                              // displayCommands
+                  + sizeof(uint64_t) * 2 + sizeof(unsigned int)                  // frame start, end, remainder
+                  + sizeof(unsigned int) * 2                                     // audio sample remainder, count
+                  + sizeof(audioAccumulator) + sizeof(uint64_t)                  // audio accumulator
+                  + sizeof(uint8_t) + sizeof(uint8_t) + sizeof(uint64_t)         // speaker pins and tone
+                  + sizeof(ledLitCycles) + sizeof(uint64_t) + sizeof(ArduousRgbLed)  // LED integration
+                  + sizeof(uint8_t) + sizeof(uint64_t)                           // EEPROM master write enable
+                  + sizeof(uint8_t) + sizeof(uint64_t)                           // fast boot
+                  + FX_FLASH_STATE_SIZE                                          // fxFlash
 	          + getEEPROMSize()
 	;
     return size;
 }
 
 bool Arduous::save(void* data, size_t size) {
+    if (!cpu || size < getSaveSize()) {
+        return false;
+    }
     auto* buffer = static_cast<uint8_t*>(data);
+    memcpy(buffer, &SAVE_STATE_MAGIC, sizeof(uint32_t));
+    buffer += sizeof(uint32_t);
+    memcpy(buffer, &SAVE_STATE_VERSION, sizeof(uint32_t));
+    buffer += sizeof(uint32_t);
+    saveCycleTimers(buffer);
+    savePeripheralState(buffer);
+    saveInterrupts(buffer);
     memcpy(buffer, &cpu->state, sizeof(int));
     buffer += sizeof(int);
     memcpy(buffer, &cpu->cycle, sizeof(avr_cycle_count_t));
@@ -330,14 +1542,6 @@ bool Arduous::save(void* data, size_t size) {
     buffer += sizeof(avr_flashaddr_t);
     memcpy(buffer, cpu->data, cpu->ramend + 1);
     buffer += cpu->ramend + 1;
-    // TODO(jmaroeder): cpu->cycle_timers
-    // for (int i = 0; i < MAX_CYCLE_TIMERS; i++) {
-    //     memcpy(buffer, &cpu->cycle_timers.timer_slots[i].when, sizeof(avr_cycle_count_t));
-    //     buffer += sizeof(avr_cycle_count_t);
-    // }
-
-    // TODO(jmaroeder): cpu->interrupts
-    // TODO(jmaroeder): cpu->eeprom?
 
     memcpy(buffer, &screen.cursor, sizeof(ssd1306_virt_cursor_t));
     buffer += sizeof(ssd1306_virt_cursor_t);
@@ -363,6 +1567,15 @@ bool Arduous::save(void* data, size_t size) {
     buffer += sizeof(uint8_t);
     memcpy(buffer, &screen.twi_index, sizeof(uint8_t));
     buffer += sizeof(uint8_t);
+    memcpy(buffer, &displayCommands.getRegisters(), sizeof(ArduousDisplayRegisters));
+    buffer += sizeof(ArduousDisplayRegisters);
+    saveFrameState(buffer);
+    if (fxFlash) {
+        fxFlash->saveState(buffer);
+    } else {
+        memset(buffer, 0, FX_FLASH_STATE_SIZE);
+        buffer += FX_FLASH_STATE_SIZE;
+    }
     int esize = getEEPROMSize();
     memcpy(buffer, getEEPROM(), esize);
     buffer += esize;
@@ -370,7 +1583,36 @@ bool Arduous::save(void* data, size_t size) {
 }
 
 bool Arduous::load(const void* data, size_t size) {
+    if (!cpu || size < getSaveSize()) {
+        return false;
+    }
     auto* buffer = static_cast<const uint8_t*>(data);
+    uint32_t magic;
+    uint32_t version;
+    memcpy(&magic, buffer, sizeof(uint32_t));
+    buffer += sizeof(uint32_t);
+    memcpy(&version, buffer, sizeof(uint32_t));
+    buffer += sizeof(uint32_t);
+    if (magic != SAVE_STATE_MAGIC || version != SAVE_STATE_VERSION) {
+        log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Warning, "Unsupported save state (magic 0x%08x, version %u)",
+            (unsigned)magic, (unsigned)version);
+        return false;
+    }
+    // Everything that indexes into the live mcu is checked before any of the state is applied
+    learnCycleTimerSources();
+    const uint8_t* timers = buffer;
+    const uint8_t* peripherals = timers + MAX_CYCLE_TIMERS * SAVE_STATE_CYCLE_TIMER_SIZE;
+    buffer = peripherals + getPeripheralStateSize();
+    if (!checkCycleTimers(timers) || !checkPeripheralState(peripherals)) {
+        log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Warning, "Save state has timers or peripherals this mcu lacks");
+        return false;
+    }
+    if (buffer[0] != cpu->interrupts.vector_count) {
+        log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Warning, "Save state is from a different mcu");
+        return false;
+    }
+    // Raising and clearing interrupts touches their flag registers, so this comes before data space is restored
+    loadInterrupts(buffer);
     memcpy(&cpu->state, buffer, sizeof(int));
     buffer += sizeof(int);
     memcpy(&cpu->cycle, buffer, sizeof(avr_cycle_count_t));
@@ -389,6 +1631,9 @@ bool Arduous::load(const void* data, size_t size) {
     buffer += sizeof(avr_flashaddr_t);
     memcpy(cpu->data, buffer, cpu->ramend + 1);
     buffer += cpu->ramend + 1;
+    // The saved schedule is registered against the restored cycle count
+    loadCycleTimers(timers);
+    loadPeripheralState(peripherals);
 
     memcpy(&screen.cursor, buffer, sizeof(ssd1306_virt_cursor_t));
     buffer += sizeof(ssd1306_virt_cursor_t);
@@ -414,6 +1659,16 @@ bool Arduous::load(const void* data, size_t size) {
     buffer += sizeof(uint8_t);
     memcpy(&screen.twi_index, buffer, sizeof(uint8_t));
     buffer += sizeof(uint8_t);
+    ArduousDisplayRegisters registers;
+    memcpy(&registers, buffer, sizeof(ArduousDisplayRegisters));
+    buffer += sizeof(ArduousDisplayRegisters);
+    displayCommands.setRegisters(registers);
+    loadFrameState(buffer);
+    if (fxFlash) {
+        fxFlash->loadState(buffer);
+    } else {
+        buffer += FX_FLASH_STATE_SIZE;
+    }
 
     int esize = getEEPROMSize();
     memcpy(getEEPROM(), buffer, esize);
@@ -421,6 +1676,285 @@ bool Arduous::load(const void* data, size_t size) {
     return true;
 }
 
+void Arduous::saveInterrupts(uint8_t*& buffer) {
+    const avr_int_table_t& table = cpu->interrupts;
+    writeState(buffer, table.vector_count);
+    for (size_t i = 0; i < SAVE_STATE_MAX_VECTORS; i++) {
+        bool used = i < table.vector_count;
+        writeState(buffer, static_cast<uint8_t>(used ? table.vector[i]->vector : 0));
+        writeState(buffer, static_cast<uint8_t>(used && table.vector[i]->pending));
+    }
+    // Interrupts being serviced, innermost last; reti pops these
+    writeState(buffer, table.running_ptr);
+    for (size_t i = 0; i < SAVE_STATE_MAX_VECTORS; i++) {
+        writeState(buffer, static_cast<uint8_t>(i < table.running_ptr ? table.running[i]->vector : 0));
+    }
+}
+
+void Arduous::loadInterrupts(const uint8_t*& buffer) {
+    avr_int_table_t& table = cpu->interrupts;
+    uint8_t count;
+    readState(buffer, count);
+    for (size_t i = 0; i < SAVE_STATE_MAX_VECTORS; i++) {
+        uint8_t vector;
+        uint8_t pending;
+        readState(buffer, vector);
+        readState(buffer, pending);
+        if (i >= count) {
+            continue;
+        }
+        if (pending && !table.vector[i]->pending) {
+            avr_raise_interrupt(cpu, table.vector[i]);
+        } else if (!pending && table.vector[i]->pending) {
+            avr_clear_interrupt(cpu, table.vector[i]);
+        }
+    }
+    uint8_t running;
+    readState(buffer, running);
+    table.running_ptr = 0;
+    for (size_t i = 0; i < SAVE_STATE_MAX_VECTORS; i++) {
+        uint8_t vector;
+        readState(buffer, vector);
+        if (i >= running) {
+            continue;
+        }
+        for (size_t v = 0; v < table.vector_count; v++) {
+            if (table.vector[v]->vector == vector) {
+                table.running[table.running_ptr++] = table.vector[v];
+                break;
+            }
+        }
+    }
+}
+
+int Arduous::getIoModuleIndex(const void* param) {
+    int index = 0;
+    for (avr_io_t* port = cpu->io_port; port; port = port->next, index++) {
+        if (port != param) {
+            continue;
+        }
+        for (const char* kind : SAVE_STATE_TIMER_MODULES) {
+            if (strcmp(port->kind, kind) == 0) {
+                return index;
+            }
+        }
+        return -1;
+    }
+    return -1;
+}
+
+avr_io_t* Arduous::getIoModule(int index) {
+    avr_io_t* port = cpu->io_port;
+    for (; port && index > 0; index--) {
+        port = port->next;
+    }
+    return port;
+}
+
+void Arduous::learnCycleTimerSources() {
+    for (avr_cycle_timer_slot_p timer = cpu->cycle_timers.timer; timer; timer = timer->next) {
+        std::pair<avr_cycle_timer_t, int> source(timer->timer, -1);
+        if (timer->param) {
+            source.second = getIoModuleIndex(timer->param);
+            if (source.second < 0) {
+                continue;
+            }
+        }
+        if (std::find(cycleTimerSources.begin(), cycleTimerSources.end(), source) == cycleTimerSources.end() &&
+            cycleTimerSources.size() < SAVE_STATE_MAX_TIMER_SOURCES) {
+            cycleTimerSources.push_back(source);
+        }
+    }
+}
+
+void Arduous::saveCycleTimers(uint8_t*& buffer) {
+    // The timer list is ordered by when; each entry keeps its source and the cycles left, not the absolute cycle
+    learnCycleTimerSources();
+    avr_cycle_timer_slot_p timer = cpu->cycle_timers.timer;
+    for (size_t i = 0; i < MAX_CYCLE_TIMERS; i++) {
+        int16_t source = -1;
+        int64_t remaining = 0;
+        for (; timer && source < 0; timer = timer->next) {
+            int module = timer->param ? getIoModuleIndex(timer->param) : -1;
+            auto found = std::find(cycleTimerSources.begin(), cycleTimerSources.end(),
+                                   std::make_pair(timer->timer, module));
+            if ((timer->param && module < 0) || found == cycleTimerSources.end()) {
+                log(ArduousLogSubsystem::Cpu, ArduousLogLevel::Debug, "Cycle timer outside the io modules not saved");
+                continue;
+            }
+            source = static_cast<int16_t>(found - cycleTimerSources.begin());
+            remaining = timer->when > cpu->cycle ? static_cast<int64_t>(timer->when - cpu->cycle) : 0;
+        }
+        writeState(buffer, source);
+        writeState(buffer, remaining);
+    }
+}
+
+bool Arduous::checkCycleTimers(const uint8_t* buffer) {
+    for (size_t i = 0; i < MAX_CYCLE_TIMERS; i++) {
+        int16_t source;
+        int64_t remaining;
+        readState(buffer, source);
+        readState(buffer, remaining);
+        if (source < -1 || source >= static_cast<int>(cycleTimerSources.size()) || remaining < 0) {
+            return false;
+        }
+    }
+    return true;
+}
+
+void Arduous::loadCycleTimers(const uint8_t* buffer) {
+    // Drop the live session's schedule, then re-register the saved one from the sources this instance has seen
+    std::vector<std::pair<avr_cycle_timer_t, void*>> live;
+    for (avr_cycle_timer_slot_p timer = cpu->cycle_timers.timer; timer; timer = timer->next) {
+        live.emplace_back(timer->timer, timer->param);
+    }
+    for (const auto& timer : live) {
+        avr_cycle_timer_cancel(cpu, timer.first, timer.second);
+    }
+
+    for (size_t i = 0; i < MAX_CYCLE_TIMERS; i++) {
+        int16_t source;
+        int64_t remaining;
+        readState(buffer, source);
+        readState(buffer, remaining);
+        if (source < 0) {
+            continue;
+        }
+        const auto& timer = cycleTimerSources[source];
+        void* param = timer.second >= 0 ? getIoModule(timer.second) : nullptr;
+        avr_cycle_timer_register(cpu, static_cast<avr_cycle_count_t>(remaining), timer.first, param);
+    }
+}
+
+size_t Arduous::getPeripheralStateSize() {
+    size_t size = 0;
+    for (avr_io_t* port = cpu->io_port; port; port = port->next) {
+        if (strcmp(port->kind, "timer") == 0) {
+            size += SAVE_STATE_TIMER_SIZE;
+        } else if (strcmp(port->kind, "uart") == 0) {
+            size += sizeof(uart_fifo_t) * 2;
+        } else if (strcmp(port->kind, "spi") == 0) {
+            size += sizeof(uint8_t);
+        }
+    }
+    return size;
+}
+
+void Arduous::savePeripheralState(uint8_t*& buffer) {
+    // The overflow and compare schedule, FIFO contents and received byte aren't in data space
+    for (avr_io_t* port = cpu->io_port; port; port = port->next) {
+        if (strcmp(port->kind, "timer") == 0) {
+            auto* timer = reinterpret_cast<avr_timer_t*>(port);
+            writeState(buffer, timer->tov_cycles);
+            writeState(buffer, timer->tov_cycles_fract);
+            writeState(buffer, timer->phase_accumulator);
+            writeState(buffer, timer->tov_base);
+            writeState(buffer, timer->tov_top);
+            for (const auto& comp : timer->comp) {
+                writeState(buffer, comp.comp_cycles);
+            }
+        } else if (strcmp(port->kind, "uart") == 0) {
+            auto* uart = reinterpret_cast<avr_uart_t*>(port);
+            writeState(buffer, uart->input);
+            writeState(buffer, uart->output);
+        } else if (strcmp(port->kind, "spi") == 0) {
+            writeState(buffer, reinterpret_cast<avr_spi_t*>(port)->input_data_register);
+        }
+    }
+}
+
+bool Arduous::checkPeripheralState(const uint8_t* buffer) {
+    for (avr_io_t* port = cpu->io_port; port; port = port->next) {
+        if (strcmp(port->kind, "timer") == 0) {
+            buffer += SAVE_STATE_TIMER_SIZE;
+        } else if (strcmp(port->kind, "uart") == 0) {
+            // The FIFO cursors index its buffer directly
+            for (int i = 0; i < 2; i++) {
+                uart_fifo_t fifo;
+                readState(buffer, fifo);
+                size_t capacity = sizeof(fifo.buffer) / sizeof(fifo.buffer[0]);
+                if (fifo.read >= capacity || fifo.write >= capacity) {
+                    return false;
+                }
+            }
+        } else if (strcmp(port->kind, "spi") == 0) {
+            buffer += sizeof(uint8_t);
+        }
+    }
+    return true;
+}
+
+void Arduous::loadPeripheralState(const uint8_t* buffer) {
+    for (avr_io_t* port = cpu->io_port; port; port = port->next) {
+        if (strcmp(port->kind, "timer") == 0) {
+            auto* timer = reinterpret_cast<avr_timer_t*>(port);
+            readState(buffer, timer->tov_cycles);
+            readState(buffer, timer->tov_cycles_fract);
+            readState(buffer, timer->phase_accumulator);
+            readState(buffer, timer->tov_base);
+            readState(buffer, timer->tov_top);
+            for (auto& comp : timer->comp) {
+                readState(buffer, comp.comp_cycles);
+            }
+        } else if (strcmp(port->kind, "uart") == 0) {
+            auto* uart = reinterpret_cast<avr_uart_t*>(port);
+            readState(buffer, uart->input);
+            readState(buffer, uart->output);
+        } else if (strcmp(port->kind, "spi") == 0) {
+            readState(buffer, reinterpret_cast<avr_spi_t*>(port)->input_data_register);
+        }
+    }
+}
+
+void Arduous::saveFrameState(uint8_t*& buffer) {
+    writeState(buffer, frameStartCycle);
+    writeState(buffer, frameEndCycle);
+    writeState(buffer, frameCycleRemainder);
+    writeState(buffer, audioSampleRemainder);
+    writeState(buffer, audioSamplesThisFrame);
+    writeState(buffer, audioAccumulator);
+    writeState(buffer, audioAccumulatedUntil);
+    writeState(buffer, static_cast<uint8_t>(speakerPins.to_ulong()));
+    writeState(buffer, static_cast<uint8_t>(toneActive));
+    writeState(buffer, lastSpeakerChangeCycle);
+    writeState(buffer, ledLitCycles);
+    writeState(buffer, ledIntegratedUntil);
+    writeState(buffer, rgbLed);
+    writeState(buffer, static_cast<uint8_t>(eepromMasterEnabled));
+    writeState(buffer, eepromMasterEnableCycle);
+    writeState(buffer, static_cast<uint8_t>(fastBootPending));
+    writeState(buffer, fastBootLimit);
+}
+
+void Arduous::loadFrameState(const uint8_t*& buffer) {
+    uint8_t pins;
+    uint8_t tone;
+    uint8_t masterEnabled;
+    uint8_t bootPending;
+    readState(buffer, frameStartCycle);
+    readState(buffer, frameEndCycle);
+    readState(buffer, frameCycleRemainder);
+    readState(buffer, audioSampleRemainder);
+    readState(buffer, audioSamplesThisFrame);
+    readState(buffer, audioAccumulator);
+    readState(buffer, audioAccumulatedUntil);
+    readState(buffer, pins);
+    readState(buffer, tone);
+    readState(buffer, lastSpeakerChangeCycle);
+    readState(buffer, ledLitCycles);
+    readState(buffer, ledIntegratedUntil);
+    readState(buffer, rgbLed);
+    readState(buffer, masterEnabled);
+    readState(buffer, eepromMasterEnableCycle);
+    readState(buffer, bootPending);
+    readState(buffer, fastBootLimit);
+    speakerPins = std::bitset<2>(pins);
+    toneActive = tone;
+    eepromMasterEnabled = masterEnabled;
+    fastBootPending = bootPending;
+}
+
 size_t Arduous::getRamSize() {
     return cpu->ramend + 1;
 }
@@ -430,10 +1964,17 @@ void *Arduous::getRam() {
 }
 
 size_t Arduous::getEEPROMSize() {
-    return 0x400;
+    // Nothing is mapped until a program is loaded
+    if (!cpu) {
+        return 0;
+    }
+    return cpu->e2end + 1;
 }
 
 void *Arduous::getEEPROM() {
+    if (!cpu) {
+        return nullptr;
+    }
     avr_eeprom_desc_t d = {
 	.ee = 0,
 	.offset = 0,
@@ -445,29 +1986,98 @@ void *Arduous::getEEPROM() {
     return d.ee;
 }
 
-int16_t Arduous::getCurrentSpeakerSample() {
+bool Arduous::isEEPROMDirty() {
+    return eepromDirty;
+}
+
+void Arduous::setEEPROMDirtyCallback(std::function<void()> callback) {
+    eepromDirtyCallback = std::move(callback);
+}
+
+void Arduous::saveEEPROM(uint8_t* data) {
+    if (!cpu) {
+        return;
+    }
+    memcpy(data, getEEPROM(), getEEPROMSize());
+    eepromDirty = false;
+}
+
+bool Arduous::loadEEPROM(const uint8_t* data, size_t size) {
+    if (!cpu) {
+        pendingEeprom.assign(data, data + size);
+        return true;
+    }
+    if (size != getEEPROMSize()) {
+        return false;
+    }
+    avr_eeprom_desc_t d = {
+        .ee = const_cast<uint8_t*>(data),
+        .offset = 0,
+        .size = static_cast<uint32_t>(size),
+    };
+    avr_ioctl(cpu, AVR_IOCTL_EEPROM_SET, &d);
+    eepromDirty = false;
+    return true;
+}
+
+std::array<int16_t, 2> Arduous::getCurrentSpeakerSample() {
+    if (audioChannels == ArduousAudioChannels::PinsToStereo) {
+        return {static_cast<int16_t>(speakerPins[0] ? INT16_MAX : 0),
+                static_cast<int16_t>(speakerPins[1] ? INT16_MAX : 0)};
+    }
     switch (speakerPins.to_ulong()) {
         case 0:
         case 3:
-            return 0;
+            return {0, 0};
             break;
         case 1:
-            return INT16_MAX;
+            return {INT16_MAX, INT16_MAX};
             break;
         case 2:
-            return INT16_MIN;
+            return {INT16_MIN, INT16_MIN};
         default:
             throw std::runtime_error("Invalid speaker pin value");
     }
 }
 
+uint64_t Arduous::getAudioSampleCycle(unsigned int sample) {
+    return frameStartCycle + sample * (frameEndCycle - frameStartCycle) / audioSamplesThisFrame;
+}
+
 void Arduous::extendAudioBuffer() {
-    int endSampleIndex = 2 * std::min((cpu->cycle - frameStartCycle) / cyclesPerAudioSample,
-                                      static_cast<uint64_t>(audioSamplesPerVideoFrame));
-    int16_t currentSample = getCurrentSpeakerSample();
-    for (unsigned int i = audioBuffer.size(); i < endSampleIndex; i++) {
-        audioBuffer.push_back(currentSample);
+    // Place samples by their share of the frame's cycles, so any rate stays in step with the video
+    if (frameEndCycle <= frameStartCycle) {
+        return;
+    }
+    uint64_t frameCycles = frameEndCycle - frameStartCycle;
+    uint64_t elapsed = std::min(cpu->cycle - frameStartCycle, frameCycles);
+    int endSampleIndex = 2 * (elapsed * audioSamplesThisFrame / frameCycles);
+    std::array<int16_t, 2> currentSample = getCurrentSpeakerSample();
+    if (audioSynthesis == ArduousAudioSynthesis::PointSampled) {
+        for (unsigned int i = audioBuffer.size(); i < endSampleIndex; i++) {
+            audioBuffer.push_back(currentSample[i % 2]);
+        }
+        return;
     }
+
+    // The level has been held since the last pin change, so finish every sample period that ended since then
+    uint64_t now = frameStartCycle + elapsed;
+    while (audioBuffer.size() < endSampleIndex) {
+        unsigned int sample = audioBuffer.size() / 2;
+        uint64_t sampleEnd = getAudioSampleCycle(sample + 1);
+        auto period = static_cast<int64_t>(sampleEnd - getAudioSampleCycle(sample));
+        auto held = static_cast<int64_t>(sampleEnd - audioAccumulatedUntil);
+        for (unsigned int channel = 0; channel < 2; channel++) {
+            audioAccumulator[channel] += currentSample[channel] * held;
+            audioBuffer.push_back(audioAccumulator[channel] / period);
+            audioAccumulator[channel] = 0;
+        }
+        audioAccumulatedUntil = sampleEnd;
+    }
+    for (unsigned int channel = 0; channel < 2; channel++) {
+        audioAccumulator[channel] += currentSample[channel] * static_cast<int64_t>(now - audioAccumulatedUntil);
+    }
+    audioAccumulatedUntil = now;
 }
 
 void Arduous::soundPinCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
@@ -475,4 +2085,219 @@ void Arduous::soundPinCallback(struct avr_irq_t* irq, uint32_t value, void* para
     Arduous* self = pinCallbackParamT->self;
     self->extendAudioBuffer();
     self->speakerPins[pinCallbackParamT->speakerPin] = value & 0x1;
+    self->lastSpeakerChangeCycle = self->cpu->cycle;
+    if (!self->toneActive) {
+        self->toneActive = true;
+        self->emitEvent(ArduousEventType::ToneStarted, self->speakerPins.to_ulong());
+    }
+}
+
+void Arduous::serialOutputCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    self->serialOutput.push_back(value & 0xFF);
+    if (self->serialPeer) {
+        uint64_t frameCycle = std::min(self->cpu->cycle - self->frameStartCycle, self->cyclesPerVideoFrame);
+        self->serialPeer->queueLinkedSerialInput(frameCycle, value & 0xFF);
+    }
+    self->emitEvent(ArduousEventType::SerialByte, value & 0xFF);
+}
+
+void Arduous::serialXonCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    self->serialInputReady = true;
+    self->pumpSerialInput();
+}
+
+void Arduous::serialXoffCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    self->serialInputReady = false;
+}
+
+void Arduous::adcTriggerCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    avr_irq_t* adcIrqs = avr_io_getirq(self->cpu, AVR_IOCTL_ADC_GETIRQ, 0);
+    union {
+        avr_adc_mux_t mux;
+        uint32_t v;
+    } e;
+    e.v = value;
+
+    switch (e.mux.kind) {
+        case ADC_MUX_SINGLE:
+            avr_raise_irq(adcIrqs + ADC_IRQ_ADC0 + e.mux.src, self->readAdcInput(e.mux.src));
+            break;
+        case ADC_MUX_DIFF:
+            avr_raise_irq(adcIrqs + ADC_IRQ_ADC0 + e.mux.src, self->readAdcInput(e.mux.src));
+            avr_raise_irq(adcIrqs + ADC_IRQ_ADC0 + e.mux.diff, self->readAdcInput(e.mux.diff));
+            break;
+        case ADC_MUX_TEMP:
+            avr_raise_irq(adcIrqs + ADC_IRQ_TEMP, self->readAdcInput(ADC_CHANNEL_TEMPERATURE));
+            break;
+        default:
+            break;
+    }
+}
+
+void Arduous::ioWriteCallback(avr_t* avr, avr_io_addr_t address, uint8_t value, void* param) {
+    auto* hook = static_cast<IoWriteHookT*>(param);
+    uint8_t oldValue = avr->data[address];
+    if (hook->original) {
+        hook->original(avr, address, value, hook->originalParam);
+    } else {
+        avr->data[address] = value;
+    }
+    hook->hook(hook->address, oldValue, value, avr->cycle);
+}
+
+void Arduous::gpioPinCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* gpioCallbackParamT = static_cast<GpioCallbackParamT*>(param);
+    Arduous* self = gpioCallbackParamT->self;
+    if (self->pinChangeCallback) {
+        self->pinChangeCallback(gpioCallbackParamT->port, gpioCallbackParamT->pin, value & 0x1);
+    }
+    self->updateLeds();
+}
+
+void Arduous::displaySpiCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    // Chip select is active-low; data/command is high for data
+    if (self->getPinLevel(self->pins.displayChipSelect)) {
+        return;
+    }
+    self->displayByte(self->getPinLevel(self->pins.displayDataCommand), value & 0xFF);
+}
+
+void Arduous::displayTwiCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    avr_twi_msg_irq_t message;
+    message.u.v = value;
+
+    if (message.u.twi.msg & TWI_COND_START) {
+        // Only writes to our address carry display traffic; the address byte has the R/W bit in bit 0
+        self->displayTwiSelected = message.u.twi.addr == (DISPLAY_I2C_ADDRESS << 1);
+        self->displayTwiControlPending = true;
+        return;
+    }
+    if (message.u.twi.msg & TWI_COND_STOP) {
+        self->displayTwiSelected = false;
+        return;
+    }
+    if (!self->displayTwiSelected || !(message.u.twi.msg & TWI_COND_WRITE)) {
+        return;
+    }
+
+    uint8_t byte = message.u.twi.data;
+    if (self->displayTwiControlPending) {
+        // Control byte: Co (bit 7) set means one byte follows before the next control byte, D/C (bit 6) set is data
+        self->displayTwiSingleByte = byte & 0x80;
+        self->displayTwiData = byte & 0x40;
+        self->displayTwiControlPending = false;
+        return;
+    }
+    self->displayByte(self->displayTwiData, byte);
+    self->displayTwiControlPending = self->displayTwiSingleByte;
+}
+
+void Arduous::displayByte(bool data, uint8_t byte) {
+    if (!data) {
+        displayCommands.command(byte);
+        return;
+    }
+    if (displayCommands.data(displayUpdateBytes)) {
+        displayUpdateCount++;
+        displayUpdatesThisSecond++;
+        // The controller model sees this byte after us, so sample the display once the write has landed
+        blendSamplePending = frameBlending > 1;
+        updateSlowPath();
+        emitEvent(ArduousEventType::DisplayUpdated, displayUpdateCount);
+    }
+}
+
+void Arduous::eepromControlCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<Arduous*>(param);
+    uint64_t cycle = self->cpu->cycle;
+    if (!(value & (1 << EECR_EEPE))) {
+        if (value & (1 << EECR_EEMPE)) {
+            self->eepromMasterEnableCycle = cycle;
+            self->eepromMasterEnabled = true;
+        }
+        return;
+    }
+    // The write only starts if EEPE follows EEMPE within 4 cycles, otherwise the hardware ignores it
+    bool starts = self->eepromMasterEnabled && cycle - self->eepromMasterEnableCycle <= EEPROM_MASTER_ENABLE_CYCLES;
+    self->eepromMasterEnabled = false;
+    if (starts) {
+        self->eepromWritesThisSecond++;
+        uint16_t address = self->cpu->data[EEARL] | (self->cpu->data[EEARH] << 8);
+        self->emitEvent(ArduousEventType::EepromWrite, address | (self->cpu->data[EEDR] << 16));
+        if (!self->eepromDirty) {
+            self->eepromDirty = true;
+            if (self->eepromDirtyCallback) {
+                self->eepromDirtyCallback();
+            }
+        }
+    }
+}
+
+void Arduous::simavrLogger(avr_t* avr, const int level, const char* format, va_list ap) {
+    auto instance = instances().find(avr);
+    if (instance == instances().end()) {
+        if (!avr || avr->log >= level) {
+            vfprintf(level > LOG_ERROR ? stdout : stderr, format, ap);
+        }
+        return;
+    }
+
+    Arduous* self = instance->second;
+    ArduousLogLevel logLevel;
+    switch (level) {
+        case LOG_ERROR:
+            logLevel = ArduousLogLevel::Error;
+            break;
+        case LOG_WARNING:
+            logLevel = ArduousLogLevel::Warning;
+            break;
+        case LOG_OUTPUT:
+            logLevel = ArduousLogLevel::Info;
+            break;
+        case LOG_TRACE:
+            logLevel = ArduousLogLevel::Trace;
+            break;
+        default:
+            logLevel = ArduousLogLevel::Debug;
+            break;
+    }
+    bool notify = self->warningCallback && (logLevel == ArduousLogLevel::Error || logLevel == ArduousLogLevel::Warning);
+    if (!notify && !self->auditMode && !self->isLogging(ArduousLogSubsystem::Cpu, logLevel)) {
+        return;
+    }
+
+    char buffer[256];
+    vsnprintf(buffer, sizeof(buffer), format, ap);
+    std::string message;
+    // Drop the terminal colour codes and trailing newline simavr decorates its messages with
+    for (const char* c = buffer; *c; c++) {
+        if (*c == '\x1b') {
+            while (*c && *c != 'm') {
+                c++;
+            }
+            if (!*c) {
+                break;
+            }
+            continue;
+        }
+        if (*c != '\n') {
+            message += *c;
+        }
+    }
+
+    // simavr reuses the same format string for each kind of problem, which makes it a handy audit key
+    if ((logLevel == ArduousLogLevel::Error || logLevel == ArduousLogLevel::Warning) &&
+        !self->recordAudit(format, message)) {
+        return;
+    }
+    self->log(ArduousLogSubsystem::Cpu, logLevel, "%s", message.c_str());
+    if (notify) {
+        self->warningCallback(message);
+    }
 }
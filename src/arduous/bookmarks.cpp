@@ -0,0 +1,47 @@
+#include "arduous/bookmarks.h"
+
+#include <algorithm>
+
+Bookmarks::Bookmarks(Arduous& arduous) : arduous(arduous) {}
+
+bool Bookmarks::add(const std::string& name, const std::string& note) {
+    ArduousBookmark bookmark = {name, note, std::time(nullptr), std::vector<uint8_t>(arduous.getSaveSize())};
+    if (!arduous.save(bookmark.state.data(), bookmark.state.size())) {
+        return false;
+    }
+
+    // Re-using a name moves the bookmark rather than keeping two with the same name
+    auto existing = find(name);
+    if (existing != bookmarks.end()) {
+        *existing = std::move(bookmark);
+    } else {
+        bookmarks.push_back(std::move(bookmark));
+    }
+    return true;
+}
+
+bool Bookmarks::jumpTo(const std::string& name) {
+    auto bookmark = find(name);
+    if (bookmark == bookmarks.end()) {
+        return false;
+    }
+    return arduous.load(bookmark->state.data(), bookmark->state.size());
+}
+
+bool Bookmarks::remove(const std::string& name) {
+    auto bookmark = find(name);
+    if (bookmark == bookmarks.end()) {
+        return false;
+    }
+    bookmarks.erase(bookmark);
+    return true;
+}
+
+const std::vector<ArduousBookmark>& Bookmarks::list() const {
+    return bookmarks;
+}
+
+std::vector<ArduousBookmark>::iterator Bookmarks::find(const std::string& name) {
+    return std::find_if(bookmarks.begin(), bookmarks.end(),
+                        [&name](const ArduousBookmark& bookmark) { return bookmark.name == name; });
+}
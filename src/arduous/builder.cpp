@@ -0,0 +1,91 @@
+#include "arduous/builder.h"
+
+#include <utility>
+
+ArduousBuilder& ArduousBuilder::variant(ArduousHardwareVariant variant) {
+    hardwareVariant = variant;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::display(ArduousDisplayController controller) {
+    displayController = controller;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::displayInterface(ArduousDisplayInterface bus) {
+    displayBus = bus;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::clockHz(uint64_t hz) {
+    clock = hz;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::fuses(ArduousFuses fuses) {
+    fuseBytes = fuses;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::eeprom(std::vector<uint8_t> contents) {
+    eepromContents = std::move(contents);
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::fxData(std::vector<uint8_t> data, size_t saveSize) {
+    fxContents = std::move(data);
+    fxSaveSize = saveSize;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::audioSampleRate(unsigned int rate) {
+    sampleRate = rate;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::audioSynthesis(ArduousAudioSynthesis newSynthesis) {
+    synthesis = newSynthesis;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::audioChannels(ArduousAudioChannels newChannels) {
+    channels = newChannels;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::strict(bool enabled) {
+    strictMode = enabled;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::fastBoot(bool enabled) {
+    fastBootEnabled = enabled;
+    return *this;
+}
+
+ArduousBuilder& ArduousBuilder::debugChecks(bool enabled) {
+    debugChecksEnabled = enabled;
+    return *this;
+}
+
+std::unique_ptr<Arduous> ArduousBuilder::build() {
+    std::unique_ptr<Arduous> arduous(new Arduous());
+    arduous->setHardwareVariant(hardwareVariant);
+    arduous->setDisplayController(displayController);
+    arduous->setDisplayInterface(displayBus);
+    arduous->setClockFrequency(clock);
+    arduous->setFuses(fuseBytes);
+    if (!eepromContents.empty()) {
+        arduous->loadEEPROM(eepromContents.data(), eepromContents.size());
+    }
+    if (!fxContents.empty()) {
+        arduous->loadFxData(fxContents.data(), fxContents.size(), fxSaveSize);
+    }
+    arduous->setAudioSampleRate(sampleRate);
+    arduous->setAudioSynthesis(synthesis);
+    arduous->setAudioChannels(channels);
+    arduous->setAuditMode(strictMode);
+    arduous->setFastBoot(fastBootEnabled);
+    arduous->setDebugChecks(debugChecksEnabled);
+    return arduous;
+}
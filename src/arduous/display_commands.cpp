@@ -0,0 +1,153 @@
+#include "arduous/display_commands.h"
+
+#define CMD_SET_START_LINE 0x40
+#define CMD_SET_START_LINE_MASK 0xC0
+#define CMD_SET_DISPLAY_OFFSET 0xD3
+#define CMD_SET_MULTIPLEX_RATIO 0xA8
+#define CMD_CHARGE_PUMP 0x8D
+#define CHARGE_PUMP_ENABLE 0x04
+#define CMD_SET_CLOCK_DIVIDE 0xD5
+#define CMD_SET_PRE_CHARGE 0xD9
+#define CMD_SET_COLUMN_ADDRESS 0x21
+#define CMD_SET_PAGE_ADDRESS 0x22
+#define CMD_ENTIRE_DISPLAY_RESUME 0xA4
+#define CMD_ENTIRE_DISPLAY_ON 0xA5
+
+// The datasheet only gives a typical 370 kHz at the reset setting of 8; each step is roughly 22 kHz
+constexpr float OSCILLATOR_DEFAULT_HZ = 370000;
+constexpr float OSCILLATOR_STEP_HZ = 22000;
+// Display clocks per row besides the two pre-charge phases
+constexpr unsigned int ROW_CLOCKS = 50;
+
+static unsigned int getArgumentCount(uint8_t command) {
+    switch (command) {
+        case 0x20:  // memory addressing mode
+        case 0x81:  // contrast
+        case 0x8D:  // charge pump
+        case 0xA8:  // multiplex ratio
+        case 0xD3:  // display offset
+        case 0xD5:  // clock divide
+        case 0xD9:  // pre-charge period
+        case 0xDA:  // COM pins
+        case 0xDB:  // VCOMH deselect level
+            return 1;
+        case 0x21:  // column address
+        case 0x22:  // page address
+        case 0xA3:  // vertical scroll area
+            return 2;
+        case 0x29:  // vertical and horizontal scroll
+        case 0x2A:
+            return 5;
+        case 0x26:  // horizontal scroll
+        case 0x27:
+            return 6;
+        default:
+            return 0;
+    }
+}
+
+void DisplayCommandParser::reset() {
+    registers = ArduousDisplayRegisters();
+    argumentsLeft = 0;
+    dataBytes = 0;
+}
+
+void DisplayCommandParser::command(uint8_t byte) {
+    if (argumentsLeft > 0) {
+        argumentsLeft--;
+        argument(byte);
+        argumentIndex++;
+        return;
+    }
+
+    if ((byte & CMD_SET_START_LINE_MASK) == CMD_SET_START_LINE) {
+        registers.startLine = byte & 0x3F;
+        return;
+    }
+    if (byte == CMD_ENTIRE_DISPLAY_ON || byte == CMD_ENTIRE_DISPLAY_RESUME) {
+        registers.entireDisplayOn = byte == CMD_ENTIRE_DISPLAY_ON;
+        return;
+    }
+    if (byte == CMD_SET_COLUMN_ADDRESS || byte == CMD_SET_PAGE_ADDRESS) {
+        dataBytes = 0;
+    }
+    pendingCommand = byte;
+    argumentsLeft = getArgumentCount(byte);
+    argumentIndex = 0;
+}
+
+bool DisplayCommandParser::data(unsigned int frameBytes) {
+    if (++dataBytes < frameBytes) {
+        return false;
+    }
+    dataBytes = 0;
+    return true;
+}
+
+void DisplayCommandParser::argument(uint8_t byte) {
+    switch (pendingCommand) {
+        case CMD_SET_DISPLAY_OFFSET:
+            registers.displayOffset = byte & 0x3F;
+            break;
+        case CMD_SET_MULTIPLEX_RATIO:
+            // Values below 15 are invalid and ignored by the controller
+            if ((byte & 0x3F) >= 15) {
+                registers.multiplexRatio = (byte & 0x3F) + 1;
+            }
+            break;
+        case CMD_CHARGE_PUMP:
+            registers.chargePump = byte & CHARGE_PUMP_ENABLE;
+            break;
+        case CMD_SET_COLUMN_ADDRESS:
+            (argumentIndex == 0 ? registers.columnStart : registers.columnEnd) = byte & 0x7F;
+            break;
+        case CMD_SET_PAGE_ADDRESS:
+            (argumentIndex == 0 ? registers.pageStart : registers.pageEnd) = byte & 0x07;
+            break;
+        case CMD_SET_CLOCK_DIVIDE:
+            registers.clockDivide = byte;
+            break;
+        case CMD_SET_PRE_CHARGE:
+            // A zero length phase is invalid and ignored by the controller
+            if ((byte & 0x0F) && (byte & 0xF0)) {
+                registers.preCharge = byte;
+            }
+            break;
+        default:
+            break;
+    }
+}
+
+const ArduousDisplayRegisters& DisplayCommandParser::getRegisters() const {
+    return registers;
+}
+
+void DisplayCommandParser::setRegisters(const ArduousDisplayRegisters& saved) {
+    registers = saved;
+    argumentsLeft = 0;
+    dataBytes = 0;
+}
+
+float getDisplayRefreshRate(const ArduousDisplayRegisters& registers) {
+    // Frame rate = oscillator / (divide ratio * clocks per row * rows)
+    float oscillator = OSCILLATOR_DEFAULT_HZ + OSCILLATOR_STEP_HZ * ((registers.clockDivide >> 4) - 8);
+    unsigned int divide = (registers.clockDivide & 0x0F) + 1;
+    unsigned int rowClocks = ROW_CLOCKS + (registers.preCharge & 0x0F) + (registers.preCharge >> 4);
+    return oscillator / (divide * rowClocks * registers.multiplexRatio);
+}
+
+bool getDisplayRamPixel(const ArduousDisplayRegisters& registers, const ArduousDisplayScan& scan, unsigned int x,
+                        unsigned int y, unsigned int& column, unsigned int& row) {
+    // Only COM0 up to the multiplex ratio are driven, and on this panel they are the bottom rows. Remapped, the scan
+    // runs from the last driven line back to COM0, so the first row shown is at 64 - mux.
+    unsigned int undriven = DISPLAY_HEIGHT - registers.multiplexRatio;
+    if (y < undriven) {
+        return false;
+    }
+    unsigned int line = scan.comScanNormal ? DISPLAY_HEIGHT - 1 - y : y - undriven;
+    // Both the start line and the display offset move RAM rows up the panel, wrapping around
+    row = (line + registers.startLine + registers.displayOffset) % DISPLAY_HEIGHT;
+    // In page addressing mode the controller model wraps the SH1106's two overflowing columns back to the start
+    column = ((scan.segmentRemap0 ? DISPLAY_WIDTH - 1 - x : x) + scan.columnOffset) % DISPLAY_WIDTH;
+    return true;
+}
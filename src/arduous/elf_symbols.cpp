@@ -0,0 +1,83 @@
+#include "arduous/elf_symbols.h"
+
+#include <cstring>
+
+#define ELF_CLASS_32 1
+#define ELF_DATA_LITTLE_ENDIAN 1
+#define ELF_HEADER_SIZE 52
+#define ELF_SECTION_HEADER_OFFSET 0x20
+#define ELF_SECTION_HEADER_SIZE 0x2E
+#define ELF_SECTION_COUNT 0x30
+#define SECTION_TYPE 4
+#define SECTION_OFFSET 16
+#define SECTION_SIZE 20
+#define SECTION_LINK 24
+#define SECTION_TYPE_SYMBOL_TABLE 2
+#define SYMBOL_SIZE 16
+#define SYMBOL_NAME 0
+#define SYMBOL_VALUE 4
+#define SYMBOL_INFO 12
+#define SYMBOL_TYPE_OBJECT 1
+
+static const uint8_t ELF_MAGIC[] = {0x7F, 'E', 'L', 'F'};
+// The data space is at most 64K, anything above it is EEPROM or fuses
+constexpr uint32_t DATA_SPACE_SIZE = 0x10000;
+
+static uint32_t read16(const uint8_t* data) {
+    return data[0] | data[1] << 8;
+}
+
+static uint32_t read32(const uint8_t* data) {
+    return read16(data) | read16(data + 2) << 16;
+}
+
+std::map<std::string, uint32_t> readElfDataSymbols(const uint8_t* data, size_t size) {
+    std::map<std::string, uint32_t> symbols;
+    if (size < ELF_HEADER_SIZE || memcmp(data, ELF_MAGIC, sizeof(ELF_MAGIC)) != 0 || data[4] != ELF_CLASS_32 ||
+        data[5] != ELF_DATA_LITTLE_ENDIAN) {
+        return symbols;
+    }
+
+    size_t sectionHeaders = read32(data + ELF_SECTION_HEADER_OFFSET);
+    size_t sectionHeaderSize = read16(data + ELF_SECTION_HEADER_SIZE);
+    size_t sectionCount = read16(data + ELF_SECTION_COUNT);
+    if (sectionHeaderSize < SECTION_LINK + 4 || sectionHeaders > size ||
+        sectionCount > (size - sectionHeaders) / sectionHeaderSize) {
+        return symbols;
+    }
+    for (size_t i = 0; i < sectionCount; i++) {
+        const uint8_t* section = data + sectionHeaders + i * sectionHeaderSize;
+        if (read32(section + SECTION_TYPE) != SECTION_TYPE_SYMBOL_TABLE) {
+            continue;
+        }
+        size_t tableOffset = read32(section + SECTION_OFFSET);
+        size_t tableSize = read32(section + SECTION_SIZE);
+        // The symbol names are in the string table section the symbol table links to
+        size_t link = read32(section + SECTION_LINK);
+        if (link >= sectionCount || tableOffset > size || tableSize > size - tableOffset) {
+            continue;
+        }
+        const uint8_t* strings = data + sectionHeaders + link * sectionHeaderSize;
+        size_t stringsOffset = read32(strings + SECTION_OFFSET);
+        size_t stringsSize = read32(strings + SECTION_SIZE);
+        if (stringsOffset > size || stringsSize > size - stringsOffset) {
+            continue;
+        }
+
+        for (size_t s = 0; s + SYMBOL_SIZE <= tableSize; s += SYMBOL_SIZE) {
+            const uint8_t* symbol = data + tableOffset + s;
+            uint32_t value = read32(symbol + SYMBOL_VALUE);
+            size_t name = read32(symbol + SYMBOL_NAME);
+            if ((symbol[SYMBOL_INFO] & 0x0F) != SYMBOL_TYPE_OBJECT || value < ELF_DATA_SPACE_OFFSET ||
+                value - ELF_DATA_SPACE_OFFSET >= DATA_SPACE_SIZE || name >= stringsSize) {
+                continue;
+            }
+            const uint8_t* text = data + stringsOffset + name;
+            const void* end = memchr(text, 0, stringsSize - name);
+            if (end && end != text) {
+                symbols[std::string(reinterpret_cast<const char*>(text))] = value - ELF_DATA_SPACE_OFFSET;
+            }
+        }
+    }
+    return symbols;
+}
@@ -0,0 +1,198 @@
+#include "arduous/flashcart.h"
+
+#include <algorithm>
+#include <cstdlib>
+#include <cstring>
+
+#include "arduous/fx_flash.h"
+#include "arduous/ihex.h"
+#include "arduous/rom_id.h"
+
+#define HEADER_MAGIC "ARDUBOY"
+#define HEADER_MAGIC_SIZE 7
+#define HEADER_CATEGORY 7
+#define HEADER_PREVIOUS_PAGE 8
+#define HEADER_NEXT_PAGE 10
+#define HEADER_SLOT_SIZE 12
+#define HEADER_PROGRAM_SIZE 14
+#define HEADER_PROGRAM_PAGE 15
+#define HEADER_DATA_PAGE 17
+#define HEADER_SAVE_PAGE 19
+#define HEADER_HASH 25
+#define HEADER_HASH_SIZE 32
+#define HEADER_STRINGS 57
+
+// ArduboyFX programs find their data and save pages through two vectors near the start of flash, each an
+// rjmp-skipping "0x18 0x95" marker followed by the page number, which the cart builder patches in
+#define PROGRAM_DATA_VECTOR 0x14
+#define PROGRAM_SAVE_VECTOR 0x18
+
+static uint16_t readPage(const uint8_t* header, size_t offset) {
+    // Header fields are big-endian
+    return static_cast<uint16_t>(header[offset] << 8 | header[offset + 1]);
+}
+
+static void patchVector(std::vector<uint8_t>& program, size_t offset, uint16_t page) {
+    program[offset] = 0x18;
+    program[offset + 1] = 0x95;
+    program[offset + 2] = page >> 8;
+    program[offset + 3] = page & 0xFF;
+}
+
+static std::string readString(const uint8_t* header, size_t& offset) {
+    std::string result;
+    while (offset < FLASHCART_HEADER_SIZE && header[offset] != 0 && header[offset] != 0xFF) {
+        result.push_back(static_cast<char>(header[offset++]));
+    }
+    if (offset < FLASHCART_HEADER_SIZE) {
+        offset++;
+    }
+    return result;
+}
+
+static void writePage(uint8_t* header, size_t offset, uint16_t page) {
+    header[offset] = page >> 8;
+    header[offset + 1] = page & 0xFF;
+}
+
+static size_t pagesFor(size_t size, size_t alignment) {
+    return (size + alignment - 1) / alignment * (alignment / FLASHCART_HEADER_SIZE);
+}
+
+static void padTo(std::vector<uint8_t>& image, size_t alignment) {
+    image.resize((image.size() + alignment - 1) / alignment * alignment, 0xFF);
+}
+
+bool isFlashcartImage(const uint8_t* data, size_t size) {
+    return size >= FLASHCART_HEADER_SIZE && memcmp(data, HEADER_MAGIC, HEADER_MAGIC_SIZE) == 0;
+}
+
+std::vector<ArduousFlashcartSlot> parseFlashcart(const uint8_t* data, size_t size) {
+    std::vector<ArduousFlashcartSlot> slots;
+    size_t page = 0;
+    while ((page + 1) * FLASHCART_HEADER_SIZE <= size) {
+        const uint8_t* header = data + page * FLASHCART_HEADER_SIZE;
+        if (memcmp(header, HEADER_MAGIC, HEADER_MAGIC_SIZE) != 0) {
+            break;
+        }
+
+        ArduousFlashcartSlot slot;
+        slot.category = header[HEADER_CATEGORY];
+        slot.page = static_cast<uint16_t>(page);
+        slot.slotPages = readPage(header, HEADER_SLOT_SIZE);
+        uint8_t programUnits = header[HEADER_PROGRAM_SIZE];
+        slot.programSize = programUnits == 0xFF ? 0 : programUnits * FLASHCART_PROGRAM_UNIT;
+        slot.programPage = readPage(header, HEADER_PROGRAM_PAGE);
+        slot.dataPage = readPage(header, HEADER_DATA_PAGE);
+        slot.savePage = readPage(header, HEADER_SAVE_PAGE);
+        size_t offset = HEADER_STRINGS;
+        slot.title = readString(header, offset);
+        slot.version = readString(header, offset);
+        slot.developer = readString(header, offset);
+        slot.info = readString(header, offset);
+        slots.push_back(slot);
+
+        uint16_t next = readPage(header, HEADER_NEXT_PAGE);
+        if (next == FLASHCART_NO_PAGE || next <= page || slot.slotPages == 0) {
+            break;
+        }
+        page = next;
+    }
+    return slots;
+}
+
+ArduousFlashcartBuildResult buildFlashcart(const std::vector<ArduousFlashcartEntry>& entries,
+                                           std::vector<uint8_t>& image) {
+    image.clear();
+    uint16_t previous = FLASHCART_NO_PAGE;
+    for (size_t i = 0; i < entries.size(); i++) {
+        const ArduousFlashcartEntry& entry = entries[i];
+        if (!entry.titleImage.empty() && entry.titleImage.size() != FLASHCART_TITLE_SIZE) {
+            return ArduousFlashcartBuildResult::InvalidTitleImage;
+        }
+
+        std::vector<uint8_t> program;
+        if (!entry.hex.empty()) {
+            uint32_t size;
+            uint32_t base;
+            uint8_t* chunk = read_ihex_buffer(entry.hex.data(), entry.hex.size(), &size, &base);
+            if (!chunk || base != 0) {
+                free(chunk);
+                return ArduousFlashcartBuildResult::InvalidHex;
+            }
+            program.assign(chunk, chunk + size);
+            free(chunk);
+            // Program sizes are stored in 128 byte units in a single header byte
+            program.resize((program.size() + FLASHCART_PROGRAM_UNIT - 1) / FLASHCART_PROGRAM_UNIT *
+                               FLASHCART_PROGRAM_UNIT,
+                           0xFF);
+            if (program.size() / FLASHCART_PROGRAM_UNIT >= 0xFF) {
+                return ArduousFlashcartBuildResult::TooLarge;
+            }
+        }
+
+        size_t page = image.size() / FLASHCART_HEADER_SIZE;
+        size_t programPage = page + 1 + pagesFor(FLASHCART_TITLE_SIZE, FLASHCART_HEADER_SIZE);
+        size_t dataPage = programPage + pagesFor(program.size(), FLASHCART_HEADER_SIZE);
+        size_t dataEnd = dataPage + pagesFor(entry.fxData.size(), FLASHCART_HEADER_SIZE);
+        // Saves are erased a sector at a time, so they start on a 4K boundary
+        size_t savePage = pagesFor(dataEnd * FLASHCART_HEADER_SIZE, FX_FLASH_SECTOR_SIZE);
+        size_t slotEnd = entry.saveSize > 0 ? savePage + pagesFor(entry.saveSize, FX_FLASH_SECTOR_SIZE) : dataEnd;
+        // Page links are 16 bits with 0xFFFF meaning none, so the next slot must start below that
+        if (slotEnd >= FLASHCART_NO_PAGE || slotEnd * FLASHCART_HEADER_SIZE > FX_FLASH_SIZE) {
+            return ArduousFlashcartBuildResult::TooLarge;
+        }
+
+        if (!program.empty() && !entry.fxData.empty()) {
+            patchVector(program, PROGRAM_DATA_VECTOR, dataPage);
+        }
+        if (!program.empty() && entry.saveSize > 0) {
+            patchVector(program, PROGRAM_SAVE_VECTOR, savePage);
+        }
+
+        uint8_t header[FLASHCART_HEADER_SIZE];
+        memset(header, 0xFF, sizeof(header));
+        memcpy(header, HEADER_MAGIC, HEADER_MAGIC_SIZE);
+        header[HEADER_CATEGORY] = entry.category;
+        writePage(header, HEADER_PREVIOUS_PAGE, previous);
+        writePage(header, HEADER_NEXT_PAGE, i + 1 < entries.size() ? slotEnd : FLASHCART_NO_PAGE);
+        writePage(header, HEADER_SLOT_SIZE, slotEnd - page);
+        header[HEADER_PROGRAM_SIZE] = program.size() / FLASHCART_PROGRAM_UNIT;
+        writePage(header, HEADER_PROGRAM_PAGE, program.empty() ? FLASHCART_NO_PAGE : programPage);
+        writePage(header, HEADER_DATA_PAGE, entry.fxData.empty() ? FLASHCART_NO_PAGE : dataPage);
+        writePage(header, HEADER_SAVE_PAGE, entry.saveSize > 0 ? savePage : FLASHCART_NO_PAGE);
+
+        // Same hash the FX tooling writes for the program and its data
+        std::vector<uint8_t> hashed(program);
+        hashed.insert(hashed.end(), entry.fxData.begin(), entry.fxData.end());
+        std::string digest = getSha256(hashed.data(), hashed.size());
+        for (size_t b = 0; b < HEADER_HASH_SIZE; b++) {
+            header[HEADER_HASH + b] = strtoul(digest.substr(b * 2, 2).c_str(), nullptr, 16);
+        }
+
+        size_t offset = HEADER_STRINGS;
+        for (const std::string* text : {&entry.title, &entry.version, &entry.developer, &entry.info}) {
+            size_t length = std::min(text->size(), FLASHCART_HEADER_SIZE - 1 - offset);
+            memcpy(header + offset, text->data(), length);
+            offset += length;
+            header[offset] = 0;
+            if (offset < FLASHCART_HEADER_SIZE - 1) {
+                offset++;
+            }
+        }
+
+        image.insert(image.end(), header, header + sizeof(header));
+        if (entry.titleImage.empty()) {
+            image.resize(image.size() + FLASHCART_TITLE_SIZE, 0x00);
+        } else {
+            image.insert(image.end(), entry.titleImage.begin(), entry.titleImage.end());
+        }
+        image.insert(image.end(), program.begin(), program.end());
+        padTo(image, FLASHCART_HEADER_SIZE);
+        image.insert(image.end(), entry.fxData.begin(), entry.fxData.end());
+        padTo(image, FLASHCART_HEADER_SIZE);
+        image.resize(slotEnd * FLASHCART_HEADER_SIZE, 0xFF);
+        previous = page;
+    }
+    return ArduousFlashcartBuildResult::Ok;
+}
@@ -0,0 +1,371 @@
+#include "arduous/fx_flash.h"
+
+#include <algorithm>
+#include <cstdio>
+#include <cstring>
+
+#include "avr_ioport.h"
+#include "avr_spi.h"
+
+#define CMD_WRITE_ENABLE 0x06
+#define CMD_WRITE_DISABLE 0x04
+#define CMD_READ_STATUS 0x05
+#define CMD_READ_DATA 0x03
+#define CMD_FAST_READ 0x0B
+#define CMD_PAGE_PROGRAM 0x02
+#define CMD_SECTOR_ERASE 0x20
+#define CMD_BLOCK_ERASE_32K 0x52
+#define CMD_BLOCK_ERASE_64K 0xD8
+#define CMD_CHIP_ERASE 0xC7
+#define CMD_CHIP_ERASE_ALT 0x60
+#define CMD_POWER_DOWN 0xB9
+#define CMD_RELEASE_POWER_DOWN 0xAB
+#define CMD_MANUFACTURER_ID 0x90
+#define CMD_JEDEC_ID 0x9F
+
+#define STATUS_BUSY 0x01
+#define STATUS_WEL 0x02
+
+#define MANUFACTURER_WINBOND 0xEF
+#define DEVICE_ID 0x17
+#define JEDEC_MEMORY_TYPE 0x40
+#define JEDEC_CAPACITY 0x18
+
+// Typical program/erase times from the W25Q128 datasheet
+constexpr unsigned int PAGE_PROGRAM_US = 700;
+constexpr unsigned int SECTOR_ERASE_US = 45000;
+constexpr unsigned int BLOCK_ERASE_32K_US = 120000;
+constexpr unsigned int BLOCK_ERASE_64K_US = 150000;
+constexpr unsigned int CHIP_ERASE_US = 40000000;
+
+FxFlash::FxFlash() : data(FX_FLASH_SIZE, 0xFF), dirtySectors(FX_FLASH_SIZE / FX_FLASH_SECTOR_SIZE, false) {}
+
+void FxFlash::connect(avr_t* newAvr, char csPort, int csPin) {
+    avr = newAvr;
+    // The chip powers up with the cpu: deselected, idle, awake and write protected
+    selected = false;
+    command = 0;
+    byteIndex = 0;
+    writeEnabled = false;
+    poweredDown = false;
+    busyUntil = 0;
+    avr_irq_register_notify(avr_io_getirq(avr, AVR_IOCTL_SPI_GETIRQ(0), SPI_IRQ_OUTPUT), FxFlash::spiCallback, this);
+    avr_irq_register_notify(avr_io_getirq(avr, AVR_IOCTL_IOPORT_GETIRQ(csPort), csPin), FxFlash::chipSelectCallback,
+                            this);
+}
+
+bool FxFlash::load(const uint8_t* image, size_t size, uint32_t loadAddress) {
+    if (loadAddress + static_cast<uint64_t>(size) > data.size()) {
+        return false;
+    }
+    memcpy(data.data() + loadAddress, image, size);
+    return true;
+}
+
+void FxFlash::saveState(uint8_t*& buffer) {
+    uint8_t flags[3] = {selected, writeEnabled, poweredDown};
+    uint32_t index = byteIndex;
+    memcpy(buffer, &flags[0], 1);
+    memcpy(buffer + 1, &command, 1);
+    memcpy(buffer + 2, &index, 4);
+    memcpy(buffer + 6, &address, 4);
+    memcpy(buffer + 10, &flags[1], 2);
+    memcpy(buffer + 12, &busyUntil, 8);
+    buffer += FX_FLASH_STATE_SIZE;
+}
+
+void FxFlash::loadState(const uint8_t*& buffer) {
+    uint8_t flags[3];
+    uint32_t index;
+    memcpy(&flags[0], buffer, 1);
+    memcpy(&command, buffer + 1, 1);
+    memcpy(&index, buffer + 2, 4);
+    memcpy(&address, buffer + 6, 4);
+    memcpy(&flags[1], buffer + 10, 2);
+    memcpy(&busyUntil, buffer + 12, 8);
+    buffer += FX_FLASH_STATE_SIZE;
+    selected = flags[0];
+    writeEnabled = flags[1];
+    poweredDown = flags[2];
+    byteIndex = index;
+    // Addresses wrap at the end of the chip, so a state can't point past it
+    address %= FX_FLASH_SIZE;
+}
+
+uint8_t* FxFlash::getData() {
+    return data.data();
+}
+
+bool FxFlash::isSectorDirty(uint32_t sector) {
+    return sector < dirtySectors.size() && dirtySectors[sector];
+}
+
+void FxFlash::clearDirtySectors() {
+    std::fill(dirtySectors.begin(), dirtySectors.end(), false);
+    saveDirty = false;
+}
+
+void FxFlash::setSaveRegion(uint32_t address, uint32_t size) {
+    saveAddress = std::min<uint32_t>(address, data.size());
+    saveSize = std::min<uint32_t>(size, data.size() - saveAddress);
+    saveDirty = false;
+}
+
+uint32_t FxFlash::getSaveAddress() {
+    return saveAddress;
+}
+
+uint32_t FxFlash::getSaveSize() {
+    return saveSize;
+}
+
+bool FxFlash::isSaveDirty() {
+    return saveDirty;
+}
+
+void FxFlash::setSaveDirtyCallback(std::function<void()> callback) {
+    saveDirtyCallback = std::move(callback);
+}
+
+void FxFlash::setLog(ArduousLogFunction function) {
+    log = std::move(function);
+}
+
+void FxFlash::report(ArduousLogLevel level, const char* format, uint8_t value) {
+    if (log) {
+        char message[64];
+        snprintf(message, sizeof(message), format, value);
+        log(level, message);
+    }
+}
+
+static bool isSupportedCommand(uint8_t command) {
+    switch (command) {
+        case CMD_WRITE_ENABLE:
+        case CMD_WRITE_DISABLE:
+        case CMD_READ_STATUS:
+        case CMD_READ_DATA:
+        case CMD_FAST_READ:
+        case CMD_PAGE_PROGRAM:
+        case CMD_SECTOR_ERASE:
+        case CMD_BLOCK_ERASE_32K:
+        case CMD_BLOCK_ERASE_64K:
+        case CMD_CHIP_ERASE:
+        case CMD_CHIP_ERASE_ALT:
+        case CMD_POWER_DOWN:
+        case CMD_RELEASE_POWER_DOWN:
+        case CMD_MANUFACTURER_ID:
+        case CMD_JEDEC_ID:
+            return true;
+        default:
+            return false;
+    }
+}
+
+static bool isWriteCommand(uint8_t command) {
+    switch (command) {
+        case CMD_PAGE_PROGRAM:
+        case CMD_SECTOR_ERASE:
+        case CMD_BLOCK_ERASE_32K:
+        case CMD_BLOCK_ERASE_64K:
+        case CMD_CHIP_ERASE:
+        case CMD_CHIP_ERASE_ALT:
+            return true;
+        default:
+            return false;
+    }
+}
+
+bool FxFlash::isBusy() {
+    return avr && avr->cycle < busyUntil;
+}
+
+uint8_t FxFlash::getStatus() {
+    return (isBusy() ? STATUS_BUSY : 0) | (writeEnabled ? STATUS_WEL : 0);
+}
+
+void FxFlash::setBusy(unsigned int microseconds) {
+    busyUntil = avr->cycle + static_cast<avr_cycle_count_t>(avr->frequency) * microseconds / 1000000;
+}
+
+uint8_t FxFlash::transfer(uint8_t in) {
+    unsigned int index = byteIndex++;
+    if (index == 0) {
+        command = in;
+        address = 0;
+        if (!isSupportedCommand(command)) {
+            report(ArduousLogLevel::Warning, "Unsupported command 0x%02x", command);
+        }
+        // While busy or asleep the chip only answers status reads and the wake-up command
+        if (isBusy() && command != CMD_READ_STATUS) {
+            report(ArduousLogLevel::Debug, "Command 0x%02x ignored while busy", command);
+            command = 0;
+        } else if (poweredDown && command != CMD_RELEASE_POWER_DOWN) {
+            report(ArduousLogLevel::Debug, "Command 0x%02x ignored while powered down", command);
+            command = 0;
+        }
+        if (command == CMD_WRITE_ENABLE) {
+            writeEnabled = true;
+        } else if (command == CMD_WRITE_DISABLE) {
+            writeEnabled = false;
+        }
+        return 0xFF;
+    }
+
+    switch (command) {
+        case CMD_READ_STATUS:
+            return getStatus();
+        case CMD_JEDEC_ID: {
+            static const uint8_t id[] = {MANUFACTURER_WINBOND, JEDEC_MEMORY_TYPE, JEDEC_CAPACITY};
+            return index <= 3 ? id[index - 1] : 0xFF;
+        }
+        case CMD_MANUFACTURER_ID:
+            if (index <= 3) {
+                return 0xFF;
+            }
+            return (index % 2 == 0) ? MANUFACTURER_WINBOND : DEVICE_ID;
+        case CMD_RELEASE_POWER_DOWN:
+            return index >= 4 ? DEVICE_ID : 0xFF;
+        case CMD_READ_DATA:
+        case CMD_FAST_READ: {
+            if (index <= 3) {
+                address = (address << 8) | in;
+                return 0xFF;
+            }
+            unsigned int firstDataIndex = command == CMD_FAST_READ ? 5 : 4;
+            if (index < firstDataIndex) {
+                return 0xFF;
+            }
+            uint8_t value = data[address % data.size()];
+            address = (address + 1) % data.size();
+            return value;
+        }
+        case CMD_PAGE_PROGRAM: {
+            if (index <= 3) {
+                address = (address << 8) | in;
+                return 0xFF;
+            }
+            if (writeEnabled) {
+                // Programming can only clear bits, and wraps around within the 256 byte page
+                uint32_t page = address & ~static_cast<uint32_t>(FX_FLASH_PAGE_SIZE - 1);
+                uint32_t offset = (address + index - 4) % FX_FLASH_PAGE_SIZE;
+                uint32_t target = (page + offset) % data.size();
+                data[target] &= in;
+                markDirty(target, 1);
+            }
+            return 0xFF;
+        }
+        case CMD_SECTOR_ERASE:
+        case CMD_BLOCK_ERASE_32K:
+        case CMD_BLOCK_ERASE_64K:
+            if (index <= 3) {
+                address = (address << 8) | in;
+            }
+            return 0xFF;
+        default:
+            return 0xFF;
+    }
+}
+
+void FxFlash::finishCommand() {
+    // Program and erase commands take effect when chip select is released
+    if (byteIndex == 0) {
+        return;
+    }
+    bool addressed = byteIndex >= 4;
+    if (!writeEnabled && isWriteCommand(command)) {
+        report(ArduousLogLevel::Debug, "Command 0x%02x ignored without write enable", command);
+    }
+    switch (command) {
+        case CMD_PAGE_PROGRAM:
+            if (writeEnabled && byteIndex > 4) {
+                setBusy(PAGE_PROGRAM_US);
+                writeEnabled = false;
+            }
+            break;
+        case CMD_SECTOR_ERASE:
+            if (writeEnabled && addressed) {
+                erase(address & ~0xFFFU, 0x1000);
+                setBusy(SECTOR_ERASE_US);
+                writeEnabled = false;
+            }
+            break;
+        case CMD_BLOCK_ERASE_32K:
+            if (writeEnabled && addressed) {
+                erase(address & ~0x7FFFU, 0x8000);
+                setBusy(BLOCK_ERASE_32K_US);
+                writeEnabled = false;
+            }
+            break;
+        case CMD_BLOCK_ERASE_64K:
+            if (writeEnabled && addressed) {
+                erase(address & ~0xFFFFU, 0x10000);
+                setBusy(BLOCK_ERASE_64K_US);
+                writeEnabled = false;
+            }
+            break;
+        case CMD_CHIP_ERASE:
+        case CMD_CHIP_ERASE_ALT:
+            if (writeEnabled) {
+                erase(0, data.size());
+                setBusy(CHIP_ERASE_US);
+                writeEnabled = false;
+            }
+            break;
+        case CMD_POWER_DOWN:
+            poweredDown = true;
+            break;
+        case CMD_RELEASE_POWER_DOWN:
+            poweredDown = false;
+            break;
+        default:
+            break;
+    }
+}
+
+void FxFlash::erase(uint32_t start, uint32_t size) {
+    start %= data.size();
+    size = std::min<uint32_t>(size, data.size() - start);
+    memset(data.data() + start, 0xFF, size);
+    markDirty(start, size);
+}
+
+void FxFlash::markDirty(uint32_t start, uint32_t size) {
+    if (size == 0) {
+        return;
+    }
+    uint32_t lastSector = (start + size - 1) / FX_FLASH_SECTOR_SIZE;
+    for (uint32_t sector = start / FX_FLASH_SECTOR_SIZE; sector <= lastSector; sector++) {
+        dirtySectors[sector] = true;
+    }
+    bool inSave = start < saveAddress + saveSize && start + size > saveAddress;
+    if (inSave && !saveDirty) {
+        saveDirty = true;
+        if (saveDirtyCallback) {
+            saveDirtyCallback();
+        }
+    }
+}
+
+void FxFlash::spiCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<FxFlash*>(param);
+    if (!self->selected) {
+        return;
+    }
+    uint8_t reply = self->transfer(value & 0xFF);
+    avr_raise_irq(avr_io_getirq(self->avr, AVR_IOCTL_SPI_GETIRQ(0), SPI_IRQ_INPUT), reply);
+}
+
+void FxFlash::chipSelectCallback(struct avr_irq_t* irq, uint32_t value, void* param) {
+    auto* self = static_cast<FxFlash*>(param);
+    // Chip select is active-low
+    bool nowSelected = !(value & 0x1);
+    if (nowSelected == self->selected) {
+        return;
+    }
+    if (!nowSelected) {
+        self->finishCommand();
+    }
+    self->selected = nowSelected;
+    self->byteIndex = 0;
+}
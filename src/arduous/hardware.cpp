@@ -0,0 +1,60 @@
+#include "arduous/hardware.h"
+
+static const ArduousPinMapping ARDUBOY_PINS = {
+    .buttonUp = {'F', 7},
+    .buttonDown = {'F', 4},
+    .buttonLeft = {'F', 5},
+    .buttonRight = {'F', 6},
+    .buttonA = {'E', 6},
+    .buttonB = {'B', 4},
+    .ledRed = {'B', 6},
+    .ledGreen = {'B', 7},
+    .ledBlue = {'B', 5},
+    .ledTx = {'D', 5},
+    .ledRx = {'B', 0},
+    .speaker1 = {'C', 6},
+    .speaker2 = {'C', 7},
+    .displayChipSelect = {'D', 6},
+    .displayDataCommand = {'D', 4},
+    .displayReset = {'D', 7},
+    .fxChipSelect = {'D', 1},
+};
+
+ArduousPinMapping getPinMapping(ArduousHardwareVariant variant) {
+    ArduousPinMapping pins = ARDUBOY_PINS;
+    switch (variant) {
+        case ArduousHardwareVariant::Arduboy:
+            break;
+        case ArduousHardwareVariant::DevKit:
+            // The DevKit has its own button layout, a single-pin speaker and only the blue LED
+            pins.buttonUp = {'B', 4};
+            pins.buttonDown = {'B', 6};
+            pins.buttonLeft = {'B', 5};
+            pins.buttonRight = {'C', 6};
+            pins.buttonA = {'F', 7};
+            pins.buttonB = {'F', 6};
+            pins.ledRed = PIN_NONE;
+            pins.ledGreen = PIN_NONE;
+            pins.ledBlue = {'B', 0};
+            pins.ledRx = PIN_NONE;
+            pins.speaker1 = {'F', 5};
+            pins.speaker2 = PIN_NONE;
+            pins.displayChipSelect = {'D', 7};
+            pins.displayReset = {'D', 6};
+            pins.fxChipSelect = PIN_NONE;
+            break;
+        case ArduousHardwareVariant::ArduboyMini:
+            // Production wiring with the FX chip select moved to PE2
+            pins.fxChipSelect = {'E', 2};
+            break;
+        case ArduousHardwareVariant::Homemade:
+            // Pro Micro alternate wiring: PD6, PC7 and PB7 aren't broken out
+            pins.displayChipSelect = {'D', 3};
+            pins.displayReset = {'D', 1};
+            pins.ledGreen = {'D', 0};
+            pins.speaker2 = PIN_NONE;
+            pins.fxChipSelect = {'D', 2};
+            break;
+    }
+    return pins;
+}
@@ -0,0 +1,20 @@
+#include "arduous/log.h"
+
+const char* getLogSubsystemName(ArduousLogSubsystem subsystem) {
+    switch (subsystem) {
+        case ArduousLogSubsystem::Cpu:
+            return "cpu";
+        case ArduousLogSubsystem::Display:
+            return "display";
+        case ArduousLogSubsystem::Audio:
+            return "audio";
+        case ArduousLogSubsystem::Eeprom:
+            return "eeprom";
+        case ArduousLogSubsystem::Serial:
+            return "serial";
+        case ArduousLogSubsystem::Fx:
+            return "fx";
+        default:
+            return "unknown";
+    }
+}
@@ -0,0 +1,69 @@
+#include "arduous/ram_search.h"
+
+RamSearch::RamSearch(RamSearchValueType valueType) : valueType(valueType) {}
+
+void RamSearch::start(const uint8_t* ram, size_t size) {
+    previous.assign(ram, ram + size);
+    candidates.clear();
+    for (size_t address = 0; address + getValueSize() <= size; address++) {
+        candidates.push_back(address);
+    }
+}
+
+void RamSearch::filter(const uint8_t* ram, size_t size, RamSearchComparison comparison, int32_t value) {
+    std::vector<uint32_t> remaining;
+    for (uint32_t address : candidates) {
+        if (address + getValueSize() > size || address + getValueSize() > previous.size()) {
+            continue;
+        }
+        int32_t before = readValue(previous.data(), address);
+        int32_t now = readValue(ram, address);
+
+        bool keep = false;
+        switch (comparison) {
+            case RamSearchComparison::Changed:
+                keep = now != before;
+                break;
+            case RamSearchComparison::Unchanged:
+                keep = now == before;
+                break;
+            case RamSearchComparison::Increased:
+                keep = now > before;
+                break;
+            case RamSearchComparison::Decreased:
+                keep = now < before;
+                break;
+            case RamSearchComparison::EqualTo:
+                keep = now == value;
+                break;
+        }
+        if (keep) {
+            remaining.push_back(address);
+        }
+    }
+
+    candidates.swap(remaining);
+    previous.assign(ram, ram + size);
+}
+
+const std::vector<uint32_t>& RamSearch::getCandidates() const {
+    return candidates;
+}
+
+int32_t RamSearch::readValue(const uint8_t* ram, uint32_t address) const {
+    switch (valueType) {
+        case RamSearchValueType::U8:
+            return ram[address];
+        case RamSearchValueType::S8:
+            return static_cast<int8_t>(ram[address]);
+        case RamSearchValueType::U16:
+            return ram[address] | (ram[address + 1] << 8);
+        case RamSearchValueType::S16:
+            return static_cast<int16_t>(ram[address] | (ram[address + 1] << 8));
+    }
+    return 0;
+}
+
+size_t RamSearch::getValueSize() const {
+    return (valueType == RamSearchValueType::U16 || valueType == RamSearchValueType::S16) ? 2 : 1;
+}
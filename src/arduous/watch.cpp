@@ -0,0 +1,145 @@
+#include "arduous/watch.h"
+
+#include <cctype>
+#include <cstdlib>
+#include <sstream>
+#include <utility>
+
+WatchList::WatchList(size_t memorySize) : memorySize(memorySize) {}
+
+void WatchList::setSymbols(std::map<std::string, uint32_t> newSymbols) {
+    symbols = std::move(newSymbols);
+}
+
+bool WatchList::add(const std::string& expression) {
+    if (expression.size() < 3 || expression[0] != '[') {
+        return false;
+    }
+    size_t close = expression.find(']');
+    if (close == std::string::npos) {
+        return false;
+    }
+
+    std::string addressText = expression.substr(1, close - 1);
+    if (addressText.empty()) {
+        return false;
+    }
+    unsigned long address;
+    if (isdigit(static_cast<unsigned char>(addressText[0]))) {
+        char* end;
+        address = strtoul(addressText.c_str(), &end, 0);
+        if (*end != '\0') {
+            return false;
+        }
+    } else {
+        auto symbol = symbols.find(addressText);
+        if (symbol == symbols.end()) {
+            return false;
+        }
+        address = symbol->second;
+    }
+
+    Watch watch = {expression, static_cast<uint32_t>(address), 1, false};
+    std::string type = close + 1 < expression.size() ? expression.substr(close + 1) : ":u8";
+    if (type == ":u8" || type == ":s8") {
+        watch.size = 1;
+    } else if (type == ":u16" || type == ":s16") {
+        watch.size = 2;
+    } else if (type == ":u32" || type == ":s32") {
+        watch.size = 4;
+    } else {
+        return false;
+    }
+    watch.isSigned = type[1] == 's';
+    if (address + watch.size > memorySize) {
+        return false;
+    }
+
+    // Adding a watch changes the columns, so earlier samples no longer line up
+    clearSamples();
+    watches.push_back(watch);
+    return true;
+}
+
+void WatchList::clear() {
+    watches.clear();
+    clearSamples();
+}
+
+bool WatchList::empty() const {
+    return watches.empty();
+}
+
+void WatchList::setDepth(size_t frames) {
+    depth = frames > 0 ? frames : 1;
+    clearSamples();
+}
+
+void WatchList::sample(uint64_t frame, const uint8_t* ram, size_t size) {
+    Sample sample = {frame, {}};
+    for (const auto& watch : watches) {
+        uint32_t raw = 0;
+        if (watch.address + watch.size <= size) {
+            for (size_t i = 0; i < watch.size; i++) {
+                raw |= static_cast<uint32_t>(ram[watch.address + i]) << (8 * i);
+            }
+        }
+        int64_t value = raw;
+        if (watch.isSigned && (raw & (1U << (8 * watch.size - 1)))) {
+            value -= static_cast<int64_t>(1) << (8 * watch.size);
+        }
+        sample.values.push_back(value);
+    }
+    if (samples.size() < depth) {
+        samples.push_back(sample);
+    } else {
+        samples[oldestSample] = std::move(sample);
+        oldestSample = (oldestSample + 1) % depth;
+    }
+}
+
+std::string WatchList::toCsv() const {
+    std::ostringstream csv;
+    csv << "frame";
+    for (const auto& watch : watches) {
+        csv << ",\"" << watch.expression << "\"";
+    }
+    csv << "\n";
+    for (size_t i = 0; i < samples.size(); i++) {
+        const Sample& sample = getSample(i);
+        csv << sample.frame;
+        for (int64_t value : sample.values) {
+            csv << "," << value;
+        }
+        csv << "\n";
+    }
+    return csv.str();
+}
+
+std::string WatchList::toJson() const {
+    std::ostringstream json;
+    json << "{\"watches\":[";
+    for (size_t i = 0; i < watches.size(); i++) {
+        json << (i ? "," : "") << "\"" << watches[i].expression << "\"";
+    }
+    json << "],\"samples\":[";
+    for (size_t i = 0; i < samples.size(); i++) {
+        const Sample& sample = getSample(i);
+        json << (i ? "," : "") << "{\"frame\":" << sample.frame << ",\"values\":[";
+        for (size_t j = 0; j < sample.values.size(); j++) {
+            json << (j ? "," : "") << sample.values[j];
+        }
+        json << "]}";
+    }
+    json << "]}";
+    return json.str();
+}
+
+void WatchList::clearSamples() {
+    samples.clear();
+    oldestSample = 0;
+}
+
+const WatchList::Sample& WatchList::getSample(size_t index) const {
+    return samples[(oldestSample + index) % samples.size()];
+}
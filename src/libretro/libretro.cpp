@@ -1,19 +1,44 @@
 #include "libretro/libretro.h"
 
+#include <cctype>
 #include <cstdio>
 #include <cstdlib>
 #include <cstring>
+#include <map>
 #include <memory>
+#include <string>
+#include <vector>
 
 #include "arduous/arduous.h"
+#include "arduous/bookmarks.h"
 #include "sim_elf.h"
 
 constexpr int FRAME_WIDTH = 128;
 constexpr int FRAME_HEIGHT = 64;
 constexpr float FRAME_ASPECT = 2.0f;
+// Show each distinct warning at most once every few seconds
+constexpr unsigned MESSAGE_THROTTLE_FRAMES = 10 * TIMING_FPS;
+constexpr unsigned MESSAGE_DURATION_FRAMES = 3 * TIMING_FPS;
+// Burn-in fun mode: a pixel lit for this long is dimmed by the maximum amount
+constexpr uint32_t BURN_IN_SATURATION_FRAMES = 30 * 60 * TIMING_FPS;
+constexpr float BURN_IN_MAX_DIM = 0.35f;
+constexpr float GHOSTING_ATTACK_SECONDS = 0.002f;
+constexpr float GHOSTING_DECAY_SECONDS = 0.015f;
 
 uint16_t fb[FRAME_WIDTH * FRAME_HEIGHT];
-int16_t audio_buffer[TIMING_SAMPLE_RATE / TIMING_FPS * 2];
+uint32_t burn_in[FRAME_WIDTH * FRAME_HEIGHT];
+static ArduousFrame frame;
+static bool burn_in_enabled = false;
+static bool ghosting_enabled = false;
+// Output transform: mirroring is applied to the panel image first, then a clockwise rotation
+static unsigned rotation = 0;
+static bool mirror_horizontal = false;
+static bool mirror_vertical = false;
+static bool can_dupe = false;
+static bool redraw_needed = true;
+static bool bookmark_keys = false;
+static bool bookmark_add_held = false;
+static bool bookmark_jump_held = false;
 
 static inline uint16_t rgb565(uint8_t r, uint8_t g, uint8_t b) {
     return ((r >> 3U) << 11U) | ((g >> 2U) << 5U) | ((b >> 3U) << 0U);
@@ -41,21 +66,234 @@ std::unique_ptr<T> make_unique(Args&&... args) {
     return std::unique_ptr<T>(new T(std::forward<Args>(args)...));
 }
 std::unique_ptr<Arduous> arduous;
+static std::unique_ptr<Bookmarks> bookmarks;
 
-void update_video() {
-    memset(fb, BLACK, sizeof(uint16_t) * FRAME_WIDTH * FRAME_HEIGHT);
-    auto bit_fb = arduous->getVideoFrameBuffer();
+static unsigned frame_counter = 0;
+static std::map<std::string, unsigned> last_warning_frames;
+
+static void show_message(const std::string& text) {
+    struct retro_message message = {text.c_str(), MESSAGE_DURATION_FRAMES};
+    environ_cb(RETRO_ENVIRONMENT_SET_MESSAGE, &message);
+}
+
+void show_warning(const std::string& text) {
+    auto last = last_warning_frames.find(text);
+    if (last != last_warning_frames.end() && frame_counter - last->second < MESSAGE_THROTTLE_FRAMES) {
+        return;
+    }
+    last_warning_frames[text] = frame_counter;
+    show_message(text);
+}
+
+// L and R have no Arduboy button, so with the bookmark keys enabled they add a bookmark and jump back to the newest one
+static void check_bookmark_keys() {
+    bool add = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_L);
+    bool jump = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_R);
+    if (add && !bookmark_add_held) {
+        std::string name = "Bookmark " + std::to_string(bookmarks->list().size() + 1);
+        show_message(bookmarks->add(name) ? name + " added" : "Unable to add a bookmark");
+    }
+    if (jump && !bookmark_jump_held && !bookmarks->list().empty()) {
+        std::string name = bookmarks->list().back().name;
+        show_message(bookmarks->jumpTo(name) ? "Jumped to " + name : "Unable to jump to " + name);
+    }
+    bookmark_add_held = add;
+    bookmark_jump_held = jump;
+}
+
+static bool is_rotated_sideways() { return rotation == 90 || rotation == 270; }
+static int output_width() { return is_rotated_sideways() ? FRAME_HEIGHT : FRAME_WIDTH; }
+static int output_height() { return is_rotated_sideways() ? FRAME_WIDTH : FRAME_HEIGHT; }
+
+// Index in the output frame of panel pixel (x, y)
+static int transform_pixel(int x, int y) {
+    if (mirror_horizontal) {
+        x = FRAME_WIDTH - 1 - x;
+    }
+    if (mirror_vertical) {
+        y = FRAME_HEIGHT - 1 - y;
+    }
+    switch (rotation) {
+        case 90:
+            return x * FRAME_HEIGHT + (FRAME_HEIGHT - 1 - y);
+        case 180:
+            return (FRAME_HEIGHT - 1 - y) * FRAME_WIDTH + (FRAME_WIDTH - 1 - x);
+        case 270:
+            return (FRAME_WIDTH - 1 - x) * FRAME_HEIGHT + y;
+        default:
+            return y * FRAME_WIDTH + x;
+    }
+}
+
+void update_geometry() {
+    struct retro_game_geometry geometry = {
+        .base_width = static_cast<unsigned>(output_width()),
+        .base_height = static_cast<unsigned>(output_height()),
+        .max_width = FRAME_WIDTH,
+        .max_height = FRAME_WIDTH,
+        .aspect_ratio = is_rotated_sideways() ? 1.0f / FRAME_ASPECT : FRAME_ASPECT,
+    };
+    environ_cb(RETRO_ENVIRONMENT_SET_GEOMETRY, &geometry);
+}
+
+void update_video(const std::vector<uint8_t>& intensity, bool changed) {
+    // Burn-in and ghosting evolve every frame even when the game draws nothing new
+    if (can_dupe && !changed && !redraw_needed && !burn_in_enabled && !ghosting_enabled) {
+        video_cb(nullptr, output_width(), output_height(), output_width() * sizeof(uint16_t));
+        return;
+    }
+    redraw_needed = false;
     for (int y = 0; y < FRAME_HEIGHT; y++) {
         for (int x = 0; x < FRAME_WIDTH; x++) {
-            fb[y * FRAME_WIDTH + x] = bit_fb[y * FRAME_WIDTH + x] ? WHITE : BLACK;
+            int i = y * FRAME_WIDTH + x;
+            uint16_t color;
+            if (!intensity[i]) {
+                color = BLACK;
+            } else if (burn_in_enabled) {
+                if (burn_in[i] < BURN_IN_SATURATION_FRAMES) {
+                    burn_in[i]++;
+                }
+                float wear = static_cast<float>(burn_in[i]) / BURN_IN_SATURATION_FRAMES;
+                auto level = static_cast<uint8_t>(intensity[i] * (1.0f - BURN_IN_MAX_DIM * wear));
+                color = rgb565(level, level, level);
+            } else {
+                color = rgb565(intensity[i], intensity[i], intensity[i]);
+            }
+            fb[transform_pixel(x, y)] = color;
         }
     }
-    video_cb((void*)fb, FRAME_WIDTH, FRAME_HEIGHT, FRAME_WIDTH * sizeof(uint16_t));
+    video_cb((void*)fb, output_width(), output_height(), output_width() * sizeof(uint16_t));
 }
 
-void update_audio() {
-    memcpy(audio_buffer, arduous->getAudioBuffer().data(), TIMING_SAMPLE_RATE / TIMING_FPS * 2);
-    audio_batch_cb(audio_buffer, TIMING_SAMPLE_RATE / TIMING_FPS);
+void update_audio(const std::vector<int16_t>& samples) {
+    audio_batch_cb(samples.data(), samples.size() / 2);
+}
+
+void check_variables() {
+    struct retro_variable var = {"arduous_burn_in", nullptr};
+    bool enabled = environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value && !strcmp(var.value, "enabled");
+    if (enabled && !burn_in_enabled) {
+        memset(burn_in, 0, sizeof(burn_in));
+    }
+    burn_in_enabled = enabled;
+
+    var = {"arduous_audio_synthesis", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        arduous->setAudioSynthesis(!strcmp(var.value, "band-limited") ? ArduousAudioSynthesis::BandLimited
+                                                                     : ArduousAudioSynthesis::PointSampled);
+    }
+
+    var = {"arduous_frame_blending", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        arduous->setFrameBlending(strtoul(var.value, nullptr, 10));
+    }
+
+    var = {"arduous_ghosting", nullptr};
+    ghosting_enabled = environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value && !strcmp(var.value, "enabled");
+    if (ghosting_enabled) {
+        arduous->setPixelPersistence(GHOSTING_ATTACK_SECONDS, GHOSTING_DECAY_SECONDS);
+    } else {
+        arduous->setPixelPersistence(0, 0);
+    }
+
+    unsigned new_rotation = rotation;
+    var = {"arduous_rotation", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        new_rotation = strtoul(var.value, nullptr, 10);
+    }
+    var = {"arduous_mirror", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        mirror_horizontal = !strcmp(var.value, "horizontal") || !strcmp(var.value, "both");
+        mirror_vertical = !strcmp(var.value, "vertical") || !strcmp(var.value, "both");
+    }
+    if (new_rotation != rotation) {
+        rotation = new_rotation;
+        update_geometry();
+    }
+    redraw_needed = true;
+
+    var = {"arduous_fast_boot", nullptr};
+    bool fast_boot = environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value && !strcmp(var.value, "enabled");
+    arduous->setFastBoot(fast_boot);
+
+    var = {"arduous_bookmark_keys", nullptr};
+    bookmark_keys = environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value && !strcmp(var.value, "enabled");
+
+    var = {"arduous_debug_checks", nullptr};
+    bool debug_checks = environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value && !strcmp(var.value, "enabled");
+    arduous->setDebugChecks(debug_checks);
+
+    var = {"arduous_audio_channels", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        arduous->setAudioChannels(!strcmp(var.value, "pins to stereo") ? ArduousAudioChannels::PinsToStereo
+                                                                       : ArduousAudioChannels::Differential);
+    }
+}
+
+// The frontend only learns the sample rate from retro_get_system_av_info, so this is read once per game
+void check_sample_rate() {
+    struct retro_variable var = {"arduous_sample_rate", nullptr};
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE, &var) && var.value) {
+        arduous->setAudioSampleRate(strtoul(var.value, nullptr, 10));
+    }
+}
+
+void load_bootloader() {
+    const char* system_dir = nullptr;
+    if (!environ_cb(RETRO_ENVIRONMENT_GET_SYSTEM_DIRECTORY, &system_dir) || !system_dir) {
+        return;
+    }
+
+    std::string path = std::string(system_dir) + "/arduous/bootloader.hex";
+    FILE* file = fopen(path.c_str(), "rb");
+    if (!file) {
+        return;
+    }
+    std::vector<char> data;
+    char chunk[4096];
+    size_t read;
+    while ((read = fread(chunk, 1, sizeof(chunk), file)) > 0) {
+        data.insert(data.end(), chunk, chunk + read);
+    }
+    fclose(file);
+
+    if (arduous->loadBootloaderHexBuffer(data.data(), data.size())) {
+        log_cb(RETRO_LOG_INFO, "Loaded bootloader from %s\n", path.c_str());
+    } else {
+        log_cb(RETRO_LOG_WARN, "Unable to load bootloader from %s\n", path.c_str());
+    }
+}
+
+void log_message(ArduousLogSubsystem subsystem, ArduousLogLevel level, const std::string& message) {
+    enum retro_log_level retro_level;
+    switch (level) {
+        case ArduousLogLevel::Error:
+            retro_level = RETRO_LOG_ERROR;
+            break;
+        case ArduousLogLevel::Warning:
+            retro_level = RETRO_LOG_WARN;
+            break;
+        case ArduousLogLevel::Info:
+            retro_level = RETRO_LOG_INFO;
+            break;
+        default:
+            retro_level = RETRO_LOG_DEBUG;
+            break;
+    }
+    log_cb(retro_level, "[%s] %s\n", getLogSubsystemName(subsystem), message.c_str());
+}
+
+// .bin files are either FX flashcart images or raw flash dumps; everything else is Intel HEX
+static bool is_binary_file(const char* path) {
+    const char* extension = path ? strrchr(path, '.') : nullptr;
+    if (!extension) {
+        return false;
+    }
+    std::string lower(extension);
+    for (char& c : lower) {
+        c = static_cast<char>(tolower(static_cast<unsigned char>(c)));
+    }
+    return lower == ".bin";
 }
 
 unsigned retro_api_version(void) { return RETRO_API_VERSION; }
@@ -74,13 +312,48 @@ bool retro_load_game(const struct retro_game_info* info) {
         {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_RIGHT, "Right"},
         {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A, "A"},
         {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_B, "B"},
+        {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_L, "Add bookmark"},
+        {0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_R, "Jump to newest bookmark"},
         {0, RETRO_DEVICE_NONE, 0, 0, nullptr},
     };
 
     environ_cb(RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS, desc);
 
+    if (!environ_cb(RETRO_ENVIRONMENT_GET_CAN_DUPE, &can_dupe)) {
+        can_dupe = false;
+    }
+
+    check_variables();
+    check_sample_rate();
+
+    load_bootloader();
+
     if (info && info->data) {
-        arduous->loadHexBuffer((const char *)info->data, info->size);
+        const uint8_t* data = static_cast<const uint8_t*>(info->data);
+        ArduousLoadResult result;
+        if (isFlashcartImage(data, info->size)) {
+            result = arduous->loadFlashcart(data, info->size);
+        } else if (is_binary_file(info->path)) {
+            result = arduous->loadProgramBytes(data, info->size);
+        } else {
+            result = arduous->loadHexBuffer((const char*)info->data, info->size);
+        }
+        switch (result) {
+            case ArduousLoadResult::Ok:
+                break;
+            case ArduousLoadResult::InvalidHex:
+                log_cb(RETRO_LOG_ERROR, "Unable to parse the hex file.\n");
+                return false;
+            case ArduousLoadResult::TooLarge:
+                log_cb(RETRO_LOG_ERROR, "The program is larger than flash.\n");
+                return false;
+            case ArduousLoadResult::OverlapsBootloader:
+                log_cb(RETRO_LOG_ERROR, "The program overlaps the bootloader.\n");
+                return false;
+            case ArduousLoadResult::InvalidFlashcart:
+                log_cb(RETRO_LOG_ERROR, "The flashcart image has no game to boot.\n");
+                return false;
+        }
     }
 
     return true;
@@ -95,6 +368,22 @@ void retro_set_environment(retro_environment_t cb) {
 
     bool no_rom = false;
     cb(RETRO_ENVIRONMENT_SET_SUPPORT_NO_GAME, &no_rom);
+
+    struct retro_variable variables[] = {
+        {"arduous_burn_in", "OLED burn-in simulation; disabled|enabled"},
+        {"arduous_sample_rate", "Audio sample rate (restart); 48000|44100|32000"},
+        {"arduous_audio_synthesis", "Speaker synthesis; point-sampled|band-limited"},
+        {"arduous_audio_channels", "Speaker channels; differential|pins to stereo"},
+        {"arduous_frame_blending", "Grayscale frame blending; off|2|3|4"},
+        {"arduous_ghosting", "OLED pixel ghosting; disabled|enabled"},
+        {"arduous_fast_boot", "Fast-forward the bootloader on power-on; disabled|enabled"},
+        {"arduous_debug_checks", "Stack checks and PC history (slower); disabled|enabled"},
+        {"arduous_bookmark_keys", "Bookmark keys (L adds, R jumps back); disabled|enabled"},
+        {"arduous_rotation", "Display rotation; 0|90|180|270"},
+        {"arduous_mirror", "Display mirroring; off|horizontal|vertical|both"},
+        {nullptr, nullptr},
+    };
+    cb(RETRO_ENVIRONMENT_SET_VARIABLES, variables);
 }
 
 void retro_set_video_refresh(retro_video_refresh_t cb) { video_cb = cb; }
@@ -108,6 +397,7 @@ void retro_init(void) {
     unsigned level = 4;
 
     arduous = make_unique<Arduous>();
+    bookmarks = make_unique<Bookmarks>(*arduous);
 
     if (environ_cb(RETRO_ENVIRONMENT_GET_LOG_INTERFACE, &log)) {
         log_cb = log.log;
@@ -116,6 +406,9 @@ void retro_init(void) {
     }
 
     environ_cb(RETRO_ENVIRONMENT_SET_PERFORMANCE_LEVEL, &level);
+
+    arduous->setLogSink(log_message);
+    arduous->setWarningCallback(show_warning);
 }
 
 void retro_get_system_info(struct retro_system_info* info) {
@@ -126,23 +419,29 @@ void retro_get_system_info(struct retro_system_info* info) {
 #endif
     info->library_version = "0.1.0" GIT_VERSION;
     info->need_fullpath = false;
-    info->valid_extensions = "hex";  // TODO(jmaroeder): handle .arduboy ZIP files
+    info->valid_extensions = "hex|bin";  // TODO(jmaroeder): handle .arduboy ZIP files
 }
 
 void retro_get_system_av_info(struct retro_system_av_info* info) {
     memset(info, 0, sizeof(retro_system_av_info));
     info->timing.fps = TIMING_FPS;
-    info->timing.sample_rate = TIMING_SAMPLE_RATE;
-    info->geometry.base_width = FRAME_WIDTH;
-    info->geometry.base_height = FRAME_HEIGHT;
+    info->timing.sample_rate = arduous->getAudioSampleRate();
+    info->geometry.base_width = output_width();
+    info->geometry.base_height = output_height();
+    // Leave room for the rotated frame so changing rotation doesn't need a driver reinit
     info->geometry.max_width = FRAME_WIDTH;
-    info->geometry.max_height = FRAME_HEIGHT;
-    info->geometry.aspect_ratio = FRAME_ASPECT;
+    info->geometry.max_height = FRAME_WIDTH;
+    info->geometry.aspect_ratio = is_rotated_sideways() ? 1.0f / FRAME_ASPECT : FRAME_ASPECT;
 }
 
 void retro_reset(void) { arduous->reset(); }
 
 void retro_run(void) {
+    bool updated = false;
+    if (environ_cb(RETRO_ENVIRONMENT_GET_VARIABLE_UPDATE, &updated) && updated) {
+        check_variables();
+    }
+
     ArduousButtonState buttonState;
     buttonState.buttonUp = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_UP);
     buttonState.buttonDown = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_DOWN);
@@ -151,9 +450,19 @@ void retro_run(void) {
     buttonState.buttonA = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_A);
     buttonState.buttonB = input_state_cb(0, RETRO_DEVICE_JOYPAD, 0, RETRO_DEVICE_ID_JOYPAD_B);
     arduous->setButtonState(buttonState);
-    arduous->emulateFrame();
-    update_video();
-    update_audio();
+    if (bookmark_keys) {
+        check_bookmark_keys();
+    }
+    arduous->emulateFrame(frame);
+    if (ghosting_enabled) {
+        std::vector<float> levels = arduous->getPersistenceFrameBuffer();
+        for (size_t i = 0; i < levels.size(); i++) {
+            frame.intensity[i] = static_cast<uint8_t>(levels[i] * 255 + 0.5f);
+        }
+    }
+    update_video(frame.intensity, frame.displayChanged);
+    update_audio(frame.audio);
+    frame_counter++;
 }
 
 size_t retro_serialize_size(void) { return arduous->getSaveSize(); }
@@ -0,0 +1,153 @@
+#include "arduous/display_commands.h"
+
+#include <initializer_list>
+
+#include "test.h"
+
+static const ArduousDisplayRegisters& send(DisplayCommandParser& parser, std::initializer_list<uint8_t> bytes) {
+    for (uint8_t byte : bytes) {
+        parser.command(byte);
+    }
+    return parser.getRegisters();
+}
+
+TEST(displayParserTracksRegisters) {
+    DisplayCommandParser parser;
+    const ArduousDisplayRegisters& registers = send(parser, {0x41, 0xD3, 0x08, 0xA8, 0x1F, 0x8D, 0x14});
+    EXPECT(registers.startLine == 1);
+    EXPECT(registers.displayOffset == 8);
+    EXPECT(registers.multiplexRatio == 32);
+    EXPECT(registers.chargePump);
+
+    send(parser, {0x21, 0x10, 0x2F, 0x22, 0x02, 0x05, 0xA5});
+    EXPECT(registers.columnStart == 0x10);
+    EXPECT(registers.columnEnd == 0x2F);
+    EXPECT(registers.pageStart == 2);
+    EXPECT(registers.pageEnd == 5);
+    EXPECT(registers.entireDisplayOn);
+    send(parser, {0xA4});
+    EXPECT(!registers.entireDisplayOn);
+}
+
+TEST(displayParserSkipsArguments) {
+    DisplayCommandParser parser;
+    // The contrast argument looks like a start line command but must not be taken as one
+    const ArduousDisplayRegisters& registers = send(parser, {0x81, 0x7F, 0x26, 0x00, 0x00, 0x00, 0x07, 0x00, 0xFF});
+    EXPECT(registers.startLine == 0);
+    send(parser, {0x45});
+    EXPECT(registers.startLine == 5);
+}
+
+TEST(displayParserIgnoresInvalidArguments) {
+    DisplayCommandParser parser;
+    const ArduousDisplayRegisters& registers = send(parser, {0xA8, 0x05, 0xD9, 0xF0});
+    EXPECT(registers.multiplexRatio == 64);
+    EXPECT(registers.preCharge == 0x22);
+
+    send(parser, {0xA8, 0x1F});
+    parser.reset();
+    EXPECT(registers.multiplexRatio == 64);
+}
+
+TEST(displayRefreshRateFollowsSettings) {
+    ArduousDisplayRegisters registers;
+    float reset = getDisplayRefreshRate(registers);
+    EXPECT(reset > 100 && reset < 115);
+    // Halving the rows driven doubles the frame rate
+    registers.multiplexRatio = 32;
+    EXPECT(getDisplayRefreshRate(registers) > reset * 1.99f && getDisplayRefreshRate(registers) < reset * 2.01f);
+    registers.multiplexRatio = 64;
+    registers.clockDivide = 0xF0;
+    EXPECT(getDisplayRefreshRate(registers) > reset);
+}
+
+// Returns the RAM pixel shown at a panel position as column * 100 + row, or -1 for a dark row
+static int ramPixelAt(const ArduousDisplayRegisters& registers, const ArduousDisplayScan& scan, unsigned int x,
+                      unsigned int y) {
+    unsigned int column;
+    unsigned int row;
+    if (!getDisplayRamPixel(registers, scan, x, y, column, row)) {
+        return -1;
+    }
+    return column * 100 + row;
+}
+
+TEST(displayScanOrientations) {
+    ArduousDisplayRegisters registers;
+    ArduousDisplayScan scan;
+    // 0xA1 0xC8, as Arduboy2 sets up the panel
+    EXPECT(ramPixelAt(registers, scan, 0, 0) == 0);
+    EXPECT(ramPixelAt(registers, scan, 127, 63) == 12763);
+    // 0xA0 0xC8 mirrors left to right
+    scan.segmentRemap0 = true;
+    EXPECT(ramPixelAt(registers, scan, 0, 0) == 12700);
+    EXPECT(ramPixelAt(registers, scan, 127, 63) == 63);
+    // 0xA0 0xC0 turns the image upside down
+    scan.comScanNormal = true;
+    EXPECT(ramPixelAt(registers, scan, 0, 0) == 12763);
+    EXPECT(ramPixelAt(registers, scan, 127, 63) == 0);
+    // 0xA1 0xC0 flips top to bottom
+    scan.segmentRemap0 = false;
+    EXPECT(ramPixelAt(registers, scan, 0, 0) == 63);
+    EXPECT(ramPixelAt(registers, scan, 127, 63) == 12700);
+}
+
+TEST(displayScanShiftsRowsAndColumns) {
+    ArduousDisplayRegisters registers;
+    registers.startLine = 8;
+    registers.displayOffset = 2;
+    ArduousDisplayScan scan;
+    scan.columnOffset = 2;
+    EXPECT(ramPixelAt(registers, scan, 0, 0) == 210);
+    // Rows and the SH1106's overflowing columns wrap around
+    EXPECT(ramPixelAt(registers, scan, 127, 63) == 109);
+    scan.comScanNormal = true;
+    EXPECT(ramPixelAt(registers, scan, 0, 63) == 210);
+}
+
+TEST(displayScanPartialMultiplex) {
+    ArduousDisplayRegisters registers;
+    registers.multiplexRatio = 32;
+    ArduousDisplayScan scan;
+    // Remapped, the driven rows start at 64 - mux
+    EXPECT(ramPixelAt(registers, scan, 0, 31) == -1);
+    EXPECT(ramPixelAt(registers, scan, 0, 32) == 0);
+    EXPECT(ramPixelAt(registers, scan, 0, 63) == 31);
+    // Scanning from COM0, the first RAM row is at the bottom of the panel
+    scan.comScanNormal = true;
+    EXPECT(ramPixelAt(registers, scan, 0, 31) == -1);
+    EXPECT(ramPixelAt(registers, scan, 0, 32) == 31);
+    EXPECT(ramPixelAt(registers, scan, 0, 63) == 0);
+    scan.segmentRemap0 = true;
+    EXPECT(ramPixelAt(registers, scan, 0, 63) == 12700);
+    scan.comScanNormal = false;
+    EXPECT(ramPixelAt(registers, scan, 127, 32) == 0);
+}
+
+TEST(displayUpdatesFollowPageWrites) {
+    DisplayCommandParser parser;
+    // SH1106 drivers select the page and column before each 128 byte page
+    unsigned int updates = 0;
+    for (int frame = 0; frame < 2; frame++) {
+        for (uint8_t page = 0; page < 8; page++) {
+            send(parser, {static_cast<uint8_t>(0xB0 | page), 0x02, 0x10});
+            for (int i = 0; i < 128; i++) {
+                updates += parser.data(1024);
+            }
+            EXPECT(updates == static_cast<unsigned int>(frame + (page == 7)));
+        }
+    }
+}
+
+TEST(displayUpdatesRestartWithTheCursor) {
+    DisplayCommandParser parser;
+    for (int i = 0; i < 1000; i++) {
+        EXPECT(!parser.data(1024));
+    }
+    // Moving the cursor starts a new frame, other commands don't
+    send(parser, {0x21, 0x00, 0x7F, 0x22, 0x00, 0x07, 0x81, 0xFF});
+    for (int i = 0; i < 1023; i++) {
+        EXPECT(!parser.data(1024));
+    }
+    EXPECT(parser.data(1024));
+}
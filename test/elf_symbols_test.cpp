@@ -0,0 +1,66 @@
+#include "arduous/elf_symbols.h"
+
+#include <cstring>
+#include <vector>
+
+#include "test.h"
+
+static void write16(std::vector<uint8_t>& elf, size_t offset, uint32_t value) {
+    elf[offset] = value & 0xFF;
+    elf[offset + 1] = value >> 8;
+}
+
+static void write32(std::vector<uint8_t>& elf, size_t offset, uint32_t value) {
+    write16(elf, offset, value & 0xFFFF);
+    write16(elf, offset + 2, value >> 16);
+}
+
+static void writeSymbol(std::vector<uint8_t>& elf, size_t offset, uint32_t name, uint32_t value, uint8_t info) {
+    write32(elf, offset, name);
+    write32(elf, offset + 4, value);
+    elf[offset + 12] = info;
+}
+
+// A header, null/symbol table/string table sections, then four symbols and their names
+static std::vector<uint8_t> getElf() {
+    const char names[] = "\0playerX\0main\0score";
+    std::vector<uint8_t> elf(236 + sizeof(names));
+    memcpy(elf.data(), "\x7F" "ELF", 4);
+    elf[4] = 1;
+    elf[5] = 1;
+    write32(elf, 0x20, 52);
+    write16(elf, 0x2E, 40);
+    write16(elf, 0x30, 3);
+
+    write32(elf, 52 + 40 + 4, 2);
+    write32(elf, 52 + 40 + 16, 172);
+    write32(elf, 52 + 40 + 20, 64);
+    write32(elf, 52 + 40 + 24, 2);
+    write32(elf, 52 + 80 + 4, 3);
+    write32(elf, 52 + 80 + 16, 236);
+    write32(elf, 52 + 80 + 20, sizeof(names));
+
+    // Global objects and a function, which isn't a variable
+    writeSymbol(elf, 188, 1, 0x800100, 0x11);
+    writeSymbol(elf, 204, 9, 0x000100, 0x12);
+    writeSymbol(elf, 220, 14, 0x800102, 0x11);
+    memcpy(elf.data() + 236, names, sizeof(names));
+    return elf;
+}
+
+TEST(elfSymbolsReadsVariables) {
+    std::vector<uint8_t> elf = getElf();
+    std::map<std::string, uint32_t> symbols = readElfDataSymbols(elf.data(), elf.size());
+    EXPECT(symbols.size() == 2);
+    EXPECT(symbols["playerX"] == 0x100);
+    EXPECT(symbols["score"] == 0x102);
+}
+
+TEST(elfSymbolsRejectsBadFiles) {
+    std::vector<uint8_t> elf = getElf();
+    EXPECT(readElfDataSymbols(elf.data(), 40).empty());
+    // The string table runs past the end of the file
+    EXPECT(readElfDataSymbols(elf.data(), elf.size() - 1).empty());
+    elf[4] = 2;
+    EXPECT(readElfDataSymbols(elf.data(), elf.size()).empty());
+}
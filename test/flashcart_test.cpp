@@ -0,0 +1,190 @@
+#include "arduous/flashcart.h"
+
+#include <algorithm>
+#include <cstdio>
+#include <cstring>
+#include <string>
+
+#include "arduous/fx_flash.h"
+#include "arduous/rom_id.h"
+#include "test.h"
+
+// Writes a slot header at the given page, with big-endian page fields like the flashcart tools write them
+static void writeHeader(std::vector<uint8_t>& image, uint16_t page, uint16_t next, uint16_t slotPages,
+                        uint8_t programUnits, uint16_t programPage, const char* strings, size_t stringsSize) {
+    uint8_t* header = image.data() + page * FLASHCART_HEADER_SIZE;
+    memset(header, 0xFF, FLASHCART_HEADER_SIZE);
+    memcpy(header, "ARDUBOY", 7);
+    header[7] = 1;
+    header[10] = next >> 8;
+    header[11] = next & 0xFF;
+    header[12] = slotPages >> 8;
+    header[13] = slotPages & 0xFF;
+    header[14] = programUnits;
+    header[15] = programPage >> 8;
+    header[16] = programPage & 0xFF;
+    memcpy(header + 57, strings, stringsSize);
+}
+
+// Encodes a program at address zero as Intel HEX, 16 bytes per record
+static std::string toHex(const std::vector<uint8_t>& program) {
+    std::string hex;
+    char record[64];
+    for (size_t address = 0; address < program.size(); address += 16) {
+        size_t length = std::min<size_t>(16, program.size() - address);
+        unsigned int sum = length + (address >> 8) + (address & 0xFF);
+        snprintf(record, sizeof(record), ":%02X%04X00", static_cast<unsigned int>(length),
+                 static_cast<unsigned int>(address));
+        hex += record;
+        for (size_t i = 0; i < length; i++) {
+            snprintf(record, sizeof(record), "%02X", program[address + i]);
+            hex += record;
+            sum += program[address + i];
+        }
+        snprintf(record, sizeof(record), "%02X\n", (0x100 - (sum & 0xFF)) & 0xFF);
+        hex += record;
+    }
+    return hex + ":00000001FF\n";
+}
+
+static std::vector<ArduousFlashcartEntry> getEntries() {
+    ArduousFlashcartEntry category;
+    category.title = "Action";
+
+    ArduousFlashcartEntry game;
+    game.category = 1;
+    std::vector<uint8_t> program(64);
+    for (size_t i = 0; i < program.size(); i++) {
+        program[i] = i;
+    }
+    game.hex = toHex(program);
+    game.titleImage.assign(FLASHCART_TITLE_SIZE, 0x55);
+    game.fxData.assign(300, 0xA5);
+    game.saveSize = 100;
+    game.title = "Game";
+    game.version = "1.0";
+    game.developer = "Someone";
+    game.info = "A test game";
+    return {category, game};
+}
+
+TEST(flashcartParsesSlots) {
+    std::vector<uint8_t> image(8 * FLASHCART_HEADER_SIZE, 0xFF);
+    EXPECT(!isFlashcartImage(image.data(), image.size()));
+
+    const char category[] = "Action";
+    const char game[] = "Game\0" "1.0\0" "Someone\0" "A test game";
+    writeHeader(image, 0, 5, 5, 0xFF, FLASHCART_NO_PAGE, category, sizeof(category));
+    writeHeader(image, 5, FLASHCART_NO_PAGE, 3, 1, 6, game, sizeof(game));
+    EXPECT(isFlashcartImage(image.data(), image.size()));
+
+    std::vector<ArduousFlashcartSlot> slots = parseFlashcart(image.data(), image.size());
+    EXPECT(slots.size() == 2);
+    if (slots.size() != 2) {
+        return;
+    }
+    EXPECT(slots[0].page == 0);
+    EXPECT(slots[0].programSize == 0);
+    EXPECT(slots[0].title == "Action");
+    EXPECT(slots[0].version.empty());
+
+    EXPECT(slots[1].category == 1);
+    EXPECT(slots[1].page == 5);
+    EXPECT(slots[1].slotPages == 3);
+    EXPECT(slots[1].programSize == FLASHCART_PROGRAM_UNIT);
+    EXPECT(slots[1].programPage == 6);
+    EXPECT(slots[1].dataPage == FLASHCART_NO_PAGE);
+    EXPECT(slots[1].title == "Game");
+    EXPECT(slots[1].version == "1.0");
+    EXPECT(slots[1].developer == "Someone");
+    EXPECT(slots[1].info == "A test game");
+}
+
+TEST(flashcartStopsAtMissingHeader) {
+    std::vector<uint8_t> image(4 * FLASHCART_HEADER_SIZE, 0xFF);
+    const char title[] = "Only";
+    // The next page points past the end of the image
+    writeHeader(image, 0, 9, 2, 0xFF, FLASHCART_NO_PAGE, title, sizeof(title));
+    EXPECT(parseFlashcart(image.data(), image.size()).size() == 1);
+    // A truncated image has no complete header to read
+    EXPECT(parseFlashcart(image.data(), FLASHCART_HEADER_SIZE - 1).empty());
+}
+
+TEST(flashcartRoundTrip) {
+    std::vector<uint8_t> image;
+    EXPECT(buildFlashcart(getEntries(), image) == ArduousFlashcartBuildResult::Ok);
+    EXPECT(isFlashcartImage(image.data(), image.size()));
+
+    std::vector<ArduousFlashcartSlot> slots = parseFlashcart(image.data(), image.size());
+    EXPECT(slots.size() == 2);
+    if (slots.size() != 2) {
+        return;
+    }
+
+    // Header and title screen only
+    EXPECT(slots[0].page == 0);
+    EXPECT(slots[0].slotPages == 5);
+    EXPECT(slots[0].programSize == 0);
+    EXPECT(slots[0].programPage == FLASHCART_NO_PAGE);
+    EXPECT(slots[0].dataPage == FLASHCART_NO_PAGE);
+    EXPECT(slots[0].savePage == FLASHCART_NO_PAGE);
+    EXPECT(slots[0].title == "Action");
+
+    // The program is padded to 128 bytes, the data takes two pages and the save starts on the next 4K sector
+    EXPECT(slots[1].category == 1);
+    EXPECT(slots[1].page == 5);
+    EXPECT(slots[1].programSize == 128);
+    EXPECT(slots[1].programPage == 10);
+    EXPECT(slots[1].dataPage == 11);
+    EXPECT(slots[1].savePage == 16);
+    EXPECT(slots[1].slotPages == 27);
+    EXPECT(slots[1].title == "Game");
+    EXPECT(slots[1].version == "1.0");
+    EXPECT(slots[1].developer == "Someone");
+    EXPECT(slots[1].info == "A test game");
+    EXPECT(image.size() == 32 * FLASHCART_HEADER_SIZE);
+}
+
+TEST(flashcartPatchesProgramVectors) {
+    std::vector<uint8_t> image;
+    EXPECT(buildFlashcart(getEntries(), image) == ArduousFlashcartBuildResult::Ok);
+    EXPECT(image.size() == 32 * FLASHCART_HEADER_SIZE);
+    if (image.size() != 32 * FLASHCART_HEADER_SIZE) {
+        return;
+    }
+
+    const uint8_t* program = image.data() + 10 * FLASHCART_HEADER_SIZE;
+    const uint8_t dataVector[] = {0x18, 0x95, 0x00, 11};
+    const uint8_t saveVector[] = {0x18, 0x95, 0x00, 16};
+    EXPECT(std::equal(dataVector, dataVector + 4, program + 0x14));
+    EXPECT(std::equal(saveVector, saveVector + 4, program + 0x18));
+    EXPECT(program[0x13] == 0x13 && program[0x1C] == 0x1C);
+    EXPECT(image[11 * FLASHCART_HEADER_SIZE] == 0xA5);
+    EXPECT(image[6 * FLASHCART_HEADER_SIZE] == 0x55);
+
+    // The header hash covers the patched program followed by the data
+    std::vector<uint8_t> hashed(program, program + 128);
+    hashed.insert(hashed.end(), 300, 0xA5);
+    std::string digest = getSha256(hashed.data(), hashed.size());
+    const uint8_t* header = image.data() + 5 * FLASHCART_HEADER_SIZE;
+    char byte[3];
+    snprintf(byte, sizeof(byte), "%02x", header[25]);
+    EXPECT(digest.substr(0, 2) == byte);
+    snprintf(byte, sizeof(byte), "%02x", header[56]);
+    EXPECT(digest.substr(62, 2) == byte);
+}
+
+TEST(flashcartRejectsBadEntries) {
+    std::vector<uint8_t> image;
+    std::vector<ArduousFlashcartEntry> entries = getEntries();
+    entries[1].titleImage.resize(100);
+    EXPECT(buildFlashcart(entries, image) == ArduousFlashcartBuildResult::InvalidTitleImage);
+
+    entries = getEntries();
+    entries[1].hex = "not hex";
+    EXPECT(buildFlashcart(entries, image) == ArduousFlashcartBuildResult::InvalidHex);
+
+    entries = getEntries();
+    entries[1].saveSize = FX_FLASH_SIZE;
+    EXPECT(buildFlashcart(entries, image) == ArduousFlashcartBuildResult::TooLarge);
+}
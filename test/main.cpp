@@ -0,0 +1,25 @@
+#include <cstdio>
+
+#include "test.h"
+
+static int failures = 0;
+
+std::vector<TestCase>& getTestCases() {
+    static std::vector<TestCase> cases;
+    return cases;
+}
+
+void failTest(const char* file, int line, const char* expression) {
+    fprintf(stderr, "%s:%d: expected %s\n", file, line, expression);
+    failures++;
+}
+
+int main() {
+    for (const auto& test : getTestCases()) {
+        int before = failures;
+        test.body();
+        printf("%s %s\n", failures == before ? "PASS" : "FAIL", test.name);
+    }
+    printf("%zu tests, %d failed checks\n", getTestCases().size(), failures);
+    return failures == 0 ? 0 : 1;
+}
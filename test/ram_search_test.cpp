@@ -0,0 +1,38 @@
+#include "arduous/ram_search.h"
+
+#include "test.h"
+
+TEST(ramSearchNarrowsCandidates) {
+    uint8_t ram[] = {1, 2, 3, 4};
+    RamSearch search;
+    search.start(ram, sizeof(ram));
+    EXPECT(search.getCandidates().size() == 4);
+
+    ram[1] = 5;
+    ram[2] = 0;
+    search.filter(ram, sizeof(ram), RamSearchComparison::Changed);
+    EXPECT(search.getCandidates() == std::vector<uint32_t>({1, 2}));
+
+    ram[1] = 6;
+    search.filter(ram, sizeof(ram), RamSearchComparison::Increased);
+    EXPECT(search.getCandidates() == std::vector<uint32_t>({1}));
+
+    search.filter(ram, sizeof(ram), RamSearchComparison::EqualTo, 7);
+    EXPECT(search.getCandidates().empty());
+}
+
+TEST(ramSearchReadsLittleEndianValues) {
+    uint8_t ram[] = {0xFE, 0xFF, 0x34, 0x12};
+    RamSearch unsignedSearch(RamSearchValueType::U16);
+    unsignedSearch.start(ram, sizeof(ram));
+    // A 16 bit value can't start on the last byte
+    EXPECT(unsignedSearch.getCandidates().size() == 3);
+    EXPECT(unsignedSearch.readValue(ram, 2) == 0x1234);
+    EXPECT(unsignedSearch.readValue(ram, 0) == 0xFFFE);
+
+    RamSearch signedSearch(RamSearchValueType::S16);
+    EXPECT(signedSearch.readValue(ram, 0) == -2);
+    signedSearch.start(ram, sizeof(ram));
+    signedSearch.filter(ram, sizeof(ram), RamSearchComparison::EqualTo, -2);
+    EXPECT(signedSearch.getCandidates() == std::vector<uint32_t>({0}));
+}
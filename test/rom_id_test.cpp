@@ -0,0 +1,34 @@
+#include "arduous/rom_id.h"
+
+#include <string>
+
+#include "test.h"
+
+static uint32_t crc32(const std::string& text) {
+    return getCrc32(reinterpret_cast<const uint8_t*>(text.data()), text.size());
+}
+
+static std::string sha256(const std::string& text) {
+    return getSha256(reinterpret_cast<const uint8_t*>(text.data()), text.size());
+}
+
+TEST(crc32MatchesCheckValue) {
+    EXPECT(crc32("") == 0);
+    EXPECT(crc32("abc") == 0x352441C2);
+    EXPECT(crc32("123456789") == 0xCBF43926);
+}
+
+TEST(sha256MatchesNistVectors) {
+    EXPECT(sha256("") == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    EXPECT(sha256("abc") == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    EXPECT(sha256("abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq") ==
+           "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+    EXPECT(sha256(std::string(1000000, 'a')) == "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0");
+}
+
+TEST(sha256HandlesPaddingBoundaries) {
+    // 55 bytes is the longest message that fits one block with its padding, 56 spills into a second
+    EXPECT(sha256(std::string(55, 'a')) == "9f4390f8d30c2dd92ec9f095b65e2b9ae9b0a925a5258e241c9f1e910f734318");
+    EXPECT(sha256(std::string(56, 'a')) == "b35439a4ac6f0948b6d6f9e3c6af0f5f590ce20f1bde7090ef7970686ec6738a");
+    EXPECT(sha256(std::string(64, 'a')) == "ffe054fe7ae0cb6dc65c3af9b61d5209f439851db43d0ba5997337df154668eb");
+}
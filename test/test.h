@@ -0,0 +1,33 @@
+#ifndef ARDUOUS_TEST_H
+#define ARDUOUS_TEST_H
+
+#include <cstdio>
+#include <functional>
+#include <vector>
+
+// Minimal self-registering tests, so the unit test target doesn't need a framework
+struct TestCase {
+    const char* name;
+    std::function<void()> body;
+};
+
+std::vector<TestCase>& getTestCases();
+void failTest(const char* file, int line, const char* expression);
+
+struct TestRegistration {
+    TestRegistration(const char* name, std::function<void()> body) { getTestCases().push_back({name, body}); }
+};
+
+#define TEST(name)                                           \
+    static void name();                                      \
+    static TestRegistration name##Registration(#name, name); \
+    static void name()
+
+#define EXPECT(expression)                             \
+    do {                                               \
+        if (!(expression)) {                           \
+            failTest(__FILE__, __LINE__, #expression); \
+        }                                              \
+    } while (0)
+
+#endif
@@ -0,0 +1,73 @@
+#include "arduous/watch.h"
+
+#include "test.h"
+
+TEST(watchListParsesExpressions) {
+    WatchList watches;
+    EXPECT(watches.empty());
+    EXPECT(watches.add("[0x100]"));
+    EXPECT(watches.add("[0x102]:s16"));
+    EXPECT(!watches.empty());
+
+    EXPECT(!watches.add("0x100"));
+    EXPECT(!watches.add("[0x100"));
+    EXPECT(!watches.add("[zz]:u8"));
+    EXPECT(!watches.add("[0x100]:u64"));
+    // The last byte of data space holds a u8 but not a u16
+    EXPECT(watches.add("[0xAFF]:u8"));
+    EXPECT(!watches.add("[0xAFF]:u16"));
+}
+
+TEST(watchListSamplesValues) {
+    uint8_t ram[WATCH_MEMORY_SIZE] = {};
+    ram[0x100] = 0x2A;
+    ram[0x102] = 0xFE;
+    ram[0x103] = 0xFF;
+
+    WatchList watches;
+    watches.add("[0x100]");
+    watches.add("[0x102]:s16");
+    watches.sample(7, ram, sizeof(ram));
+    ram[0x100] = 0x2B;
+    watches.sample(8, ram, sizeof(ram));
+
+    EXPECT(watches.toCsv() == "frame,\"[0x100]\",\"[0x102]:s16\"\n7,42,-2\n8,43,-2\n");
+    EXPECT(watches.toJson() ==
+           "{\"watches\":[\"[0x100]\",\"[0x102]:s16\"],\"samples\":[{\"frame\":7,\"values\":[42,-2]},"
+           "{\"frame\":8,\"values\":[43,-2]}]}");
+
+    watches.clear();
+    EXPECT(watches.empty());
+    EXPECT(watches.toCsv() == "frame\n");
+}
+
+TEST(watchListKeepsTheNewestSamples) {
+    uint8_t ram[WATCH_MEMORY_SIZE] = {};
+    WatchList watches;
+    watches.add("[0x100]");
+    watches.setDepth(2);
+    for (uint8_t frame = 1; frame <= 5; frame++) {
+        ram[0x100] = frame * 10;
+        watches.sample(frame, ram, sizeof(ram));
+    }
+    EXPECT(watches.toCsv() == "frame,\"[0x100]\"\n4,40\n5,50\n");
+    EXPECT(watches.toJson() ==
+           "{\"watches\":[\"[0x100]\"],\"samples\":[{\"frame\":4,\"values\":[40]},{\"frame\":5,\"values\":[50]}]}");
+
+    watches.setDepth(3);
+    EXPECT(watches.toCsv() == "frame,\"[0x100]\"\n");
+}
+
+TEST(watchListResolvesSymbols) {
+    uint8_t ram[WATCH_MEMORY_SIZE] = {};
+    ram[0x100] = 0xFF;
+    ram[0x101] = 0xFF;
+
+    WatchList watches;
+    EXPECT(!watches.add("[playerX]:s16"));
+    watches.setSymbols({{"playerX", 0x100}, {"past", 0xB00}});
+    EXPECT(watches.add("[playerX]:s16"));
+    EXPECT(!watches.add("[past]"));
+    watches.sample(1, ram, sizeof(ram));
+    EXPECT(watches.toCsv() == "frame,\"[playerX]:s16\"\n1,-1\n");
+}